@@ -1,6 +1,42 @@
-use std::io::Write;
+//! Generators writing `JsonValue`s out as JSON, and the `Generator` trait
+//! for writing custom ones.
+//!
+//! ```
+//! # #[macro_use] extern crate json;
+//! # fn main() {
+//! # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
+//! use std::io;
+//! use json::codegen::Generator;
+//!
+//! // Writes compact JSON, with all keys in upper case
+//! struct UpperKeys {
+//!     code: Vec<u8>,
+//! }
+//!
+//! impl Generator for UpperKeys {
+//!     type T = Vec<u8>;
+//!
+//!     fn get_writer(&mut self) -> &mut Vec<u8> {
+//!         &mut self.code
+//!     }
+//!
+//!     fn write_key(&mut self, key: &str) -> io::Result<()> {
+//!         self.write_string(&key.to_uppercase())
+//!     }
+//! }
+//!
+//! let mut gen = UpperKeys { code: Vec::new() };
+//! gen.write_json(&object!{ "id" => 1, "tags" => array!["a"] }).unwrap();
+//!
+//! assert_eq!(gen.code, br#"{"ID":1,"TAGS":["a"]}"#.to_vec());
+//! # }
+//! ```
+
+use std::io::{ self, Write };
 use std::num::FpCategory;
-use JsonValue;
+use std::{ fmt, str };
+use { JsonValue, JsonError, Integer };
+use scan;
 
 extern crate itoa;
 
@@ -35,166 +71,533 @@ static ESCAPED: [u8; 256] = [
   __, __, __, __, __, __, __, __, __, __, __, __, __, __, __, __, // F
 ];
 
+// Escape sequences of all control characters in their `\u00XX` form, used
+// for the ones that don't have a short form. Indexed by the character.
+static CONTROL_ESCAPES: [&[u8; 6]; 32] = [
+    b"\\u0000", b"\\u0001", b"\\u0002", b"\\u0003",
+    b"\\u0004", b"\\u0005", b"\\u0006", b"\\u0007",
+    b"\\u0008", b"\\u0009", b"\\u000a", b"\\u000b",
+    b"\\u000c", b"\\u000d", b"\\u000e", b"\\u000f",
+    b"\\u0010", b"\\u0011", b"\\u0012", b"\\u0013",
+    b"\\u0014", b"\\u0015", b"\\u0016", b"\\u0017",
+    b"\\u0018", b"\\u0019", b"\\u001a", b"\\u001b",
+    b"\\u001c", b"\\u001d", b"\\u001e", b"\\u001f",
+];
+
+static HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+// Writes the escape sequence of the character into `buffer` and returns its
+// length. Characters without a short escape are written in their `\uXXXX`
+// form, as a surrogate pair if they are above the Basic Multilingual Plane.
+fn escape_char(ch: char, buffer: &mut [u8; 12]) -> usize {
+    let code = ch as u32;
+
+    if code < 0x80 && ESCAPED[code as usize] != __ {
+        match ESCAPED[code as usize] {
+            UU => {
+                buffer[.. 6].copy_from_slice(CONTROL_ESCAPES[code as usize]);
+                return 6;
+            },
+            escape => {
+                buffer[0] = b'\\';
+                buffer[1] = escape;
+                return 2;
+            }
+        }
+    }
+
+    let mut units = [0u16; 2];
+    let units = ch.encode_utf16(&mut units);
+
+    for (index, unit) in units.iter().enumerate() {
+        let escape = &mut buffer[index * 6 .. index * 6 + 6];
+
+        escape[0] = b'\\';
+        escape[1] = b'u';
+        escape[2] = HEX_DIGITS[(unit >> 12) as usize & 0xF];
+        escape[3] = HEX_DIGITS[(unit >> 8) as usize & 0xF];
+        escape[4] = HEX_DIGITS[(unit >> 4) as usize & 0xF];
+        escape[5] = HEX_DIGITS[*unit as usize & 0xF];
+    }
+
+    units.len() * 6
+}
+
+// Length of the escape sequence `escape_char` writes for the character
+fn escape_len(ch: char) -> usize {
+    let code = ch as u32;
+
+    if code < 0x80 {
+        match ESCAPED[code as usize] {
+            UU | __ => 6,
+            _       => 2,
+        }
+    } else {
+        ch.len_utf16() * 6
+    }
+}
+
+/// What to write in place of numbers that JSON can't represent: NaN,
+/// positive and negative infinity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonFinite {
+    /// Write `null`.
+    Null,
+
+    /// Fail with `JsonError::NonFiniteNumber`. Writers return it wrapped in
+    /// an `io::Error` of the `InvalidData` kind.
+    Error,
+
+    /// Write `NaN`, `Infinity` and `-Infinity`, as JSON5 and JavaScript
+    /// do. The output isn't valid JSON then.
+    Literal,
+}
+
+/// Options that apply to all JSON output, compact or pretty printed. Used
+/// with `JsonValue::dump_with` and `JsonValue::to_writer_with`, and as part
+/// of `PrettyOptions`.
+///
+/// ```
+/// # #[macro_use] extern crate json;
+/// # fn main() {
+/// use json::DumpOptions;
+///
+/// let data = array!["café", "😀"];
+///
+/// assert_eq!(data.dump(), r#"["café","😀"]"#);
+/// assert_eq!(data.dump_with(DumpOptions::new().ascii_only(true)).unwrap(), r#"["caf\u00e9","\ud83d\ude00"]"#);
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpOptions {
+    ascii_only: bool,
+    html_safe: bool,
+    non_finite: NonFinite,
+}
+
+impl DumpOptions {
+    /// Only escape the characters JSON requires to be escaped, write NaN
+    /// and infinite numbers as `null`.
+    pub fn new() -> Self {
+        DumpOptions {
+            ascii_only: false,
+            html_safe: false,
+            non_finite: NonFinite::Null,
+        }
+    }
+
+    /// Escape every character outside of ASCII as `\uXXXX`, so that the
+    /// output consists of ASCII bytes only. Characters above the Basic
+    /// Multilingual Plane are escaped as surrogate pairs.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Escape `<`, `>`, `&`, `'`, U+2028 and U+2029 as `\uXXXX`, so that
+    /// the output can be embedded in HTML, including inside of `<script>`
+    /// tags, and is valid JavaScript.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// use json::DumpOptions;
+    ///
+    /// let data = object!{ "html" => "</script><b>'Tom' & 'Jerry'</b>" };
+    /// let dump = data.dump_with(DumpOptions::new().html_safe(true)).unwrap();
+    ///
+    /// assert_eq!(dump, r#"{"html":"\u003c/script\u003e\u003cb\u003e\u0027Tom\u0027 \u0026 \u0027Jerry\u0027\u003c/b\u003e"}"#);
+    /// assert_eq!(json::parse(&dump).unwrap(), data);
+    /// # }
+    /// ```
+    pub fn html_safe(mut self, html_safe: bool) -> Self {
+        self.html_safe = html_safe;
+        self
+    }
+
+    /// How to write NaN and infinite numbers, see `NonFinite`.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// use std::f64;
+    /// use json::{ DumpOptions, NonFinite, JsonError };
+    ///
+    /// let data = array![1.5, f64::NAN, f64::NEG_INFINITY];
+    ///
+    /// assert_eq!(data.dump(), "[1.5,null,null]");
+    ///
+    /// let options = DumpOptions::new().non_finite(NonFinite::Literal);
+    /// assert_eq!(data.dump_with(options).unwrap(), "[1.5,NaN,-Infinity]");
+    ///
+    /// let options = DumpOptions::new().non_finite(NonFinite::Error);
+    /// assert_eq!(data.dump_with(options), Err(JsonError::NonFiniteNumber));
+    /// # }
+    /// ```
+    pub fn non_finite(mut self, non_finite: NonFinite) -> Self {
+        self.non_finite = non_finite;
+        self
+    }
+
+    // Whether NaN and infinite numbers fail instead of being written
+    pub(crate) fn rejects_non_finite(&self) -> bool {
+        self.non_finite == NonFinite::Error
+    }
+
+    // Whether strings need checking character by character
+    #[inline(always)]
+    fn escapes_extra(&self) -> bool {
+        self.ascii_only || self.html_safe
+    }
+
+    #[inline(always)]
+    fn escapes(&self, ch: char) -> bool {
+        let code = ch as u32;
+
+        if code < 0x80 {
+            ESCAPED[code as usize] != __ || (self.html_safe && match ch {
+                '<' | '>' | '&' | '\'' => true,
+                _                      => false,
+            })
+        } else {
+            self.ascii_only || (self.html_safe && (ch == '\u{2028}' || ch == '\u{2029}'))
+        }
+    }
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions::new()
+    }
+}
+
+fn non_finite_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, JsonError::NonFiniteNumber)
+}
+
+/// Writes out `JsonValue`s as JSON. Implementors only have to provide
+/// `get_writer`, everything else has a default that writes compact JSON,
+/// same as `JsonValue::dump`. See the module documentation for an example.
+///
+/// `write_json` calls the hooks in the following order, with `first` being
+/// `true` for the first item of the container only:
+///
+/// - arrays: `begin_array`, then `begin_array_value(first)` followed by the
+///   item for every item, then `end_array(empty)`,
+/// - objects: `begin_object`, then `begin_object_key(first)`, `write_key`,
+///   `begin_object_value` followed by the value for every entry, then
+///   `end_object(empty)`,
+/// - scalars: `write_string`, `write_number`, `write_integer`, `write_bool`
+///   or `write_null`.
+///
+/// The default hooks for containers are built on the smaller `new_line`,
+/// `write_comma`, `write_empty`, `indent` and `dedent` hooks, which is all
+/// the built-in pretty printers override.
 pub trait Generator {
     type T: Write;
 
     fn get_writer(&mut self) -> &mut Self::T;
 
     #[inline(always)]
-    fn write(&mut self, slice: &[u8]) {
-        self.get_writer().write_all(slice).unwrap();
+    fn write(&mut self, slice: &[u8]) -> io::Result<()> {
+        self.get_writer().write_all(slice)
     }
 
     #[inline(always)]
-    fn write_char(&mut self, ch: u8) {
-        self.get_writer().write_all(&[ch]).unwrap();
+    fn write_char(&mut self, ch: u8) -> io::Result<()> {
+        self.get_writer().write_all(&[ch])
     }
 
-    fn write_min(&mut self, slice: &[u8], min: u8);
+    /// Called before an item of an array or an entry of an object, after
+    /// the comma, if there is one.
+    #[inline(always)]
+    fn new_line(&mut self) -> io::Result<()> { Ok(()) }
+
+    #[inline(always)]
+    fn write_comma(&mut self) -> io::Result<()> {
+        self.write_char(b',')
+    }
 
-    fn new_line(&mut self) {}
+    /// Called between the brackets of an empty array or object.
+    #[inline(always)]
+    fn write_empty(&mut self) -> io::Result<()> { Ok(()) }
 
+    /// Called when entering a non-empty array or object.
     fn indent(&mut self) {}
 
+    /// Called when leaving a non-empty array or object.
     fn dedent(&mut self) {}
 
+    /// Called once the whole document has been written by
+    /// `JsonValue::pretty`, `JsonValue::to_writer_pretty` and `JsonWriter`.
+    #[inline(always)]
+    fn end_document(&mut self) -> io::Result<()> { Ok(()) }
+
+    /// Options for escaping strings and writing numbers.
+    #[inline(always)]
+    fn dump_options(&self) -> DumpOptions {
+        DumpOptions::new()
+    }
+
+    #[inline(always)]
+    fn begin_array(&mut self) -> io::Result<()> {
+        self.write_char(b'[')
+    }
+
+    #[inline(always)]
+    fn begin_array_value(&mut self, first: bool) -> io::Result<()> {
+        if first {
+            self.indent();
+        } else {
+            try!(self.write_comma());
+        }
+
+        self.new_line()
+    }
+
+    #[inline(always)]
+    fn end_array(&mut self, empty: bool) -> io::Result<()> {
+        if empty {
+            try!(self.write_empty());
+        } else {
+            self.dedent();
+            try!(self.new_line());
+        }
+
+        self.write_char(b']')
+    }
+
+    #[inline(always)]
+    fn begin_object(&mut self) -> io::Result<()> {
+        self.write_char(b'{')
+    }
+
+    #[inline(always)]
+    fn begin_object_key(&mut self, first: bool) -> io::Result<()> {
+        self.begin_array_value(first)
+    }
+
+    #[inline(always)]
+    fn write_key(&mut self, key: &str) -> io::Result<()> {
+        self.write_string(key)
+    }
+
+    /// Called between a key and its value.
+    #[inline(always)]
+    fn begin_object_value(&mut self) -> io::Result<()> {
+        self.write_char(b':')
+    }
+
+    #[inline(always)]
+    fn end_object(&mut self, empty: bool) -> io::Result<()> {
+        if empty {
+            try!(self.write_empty());
+        } else {
+            self.dedent();
+            try!(self.new_line());
+        }
+
+        self.write_char(b'}')
+    }
+
+    #[inline(always)]
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        let literal: &[u8] = if value { b"true" } else { b"false" };
+        self.write(literal)
+    }
+
+    #[inline(always)]
+    fn write_null(&mut self) -> io::Result<()> {
+        self.write(b"null")
+    }
+
+    /// Finishes writing a string whose byte at `start` is the first one
+    /// that needs escaping, called by `write_string`.
     #[inline(never)]
-    fn write_string_complex(&mut self, string: &str, mut start: usize) {
-        self.write(string[ .. start].as_bytes());
-
-        for (index, ch) in string.bytes().enumerate().skip(start) {
-            let escape = ESCAPED[ch as usize];
-            if escape > 0 {
-                self.write(string[start .. index].as_bytes());
-                self.write(&[b'\\', escape]);
-                start = index + 1;
-            }
-            if escape == b'u' {
-                write!(self.get_writer(), "{:04x}", ch).unwrap();
+    fn write_string_complex(&mut self, string: &str, mut start: usize) -> io::Result<()> {
+        let bytes = string.as_bytes();
+
+        try!(self.write(&bytes[.. start]));
+
+        // Every byte `scan::find_special` stops at has an entry in `ESCAPED`
+        while start < bytes.len() {
+            let ch = bytes[start];
+
+            try!(match ESCAPED[ch as usize] {
+                UU     => self.write(CONTROL_ESCAPES[ch as usize]),
+                escape => self.write(&[b'\\', escape]),
+            });
+
+            let plain = start + 1;
+            start = plain + scan::find_special(&bytes[plain ..]);
+
+            try!(self.write(&bytes[plain .. start]));
+        }
+
+        self.write_char(b'"')
+    }
+
+    /// Used instead of `write_string_complex` when `DumpOptions` ask for
+    /// more characters to be escaped than JSON requires, checks the string
+    /// one character at a time.
+    #[inline(never)]
+    fn write_string_escaped(&mut self, string: &str) -> io::Result<()> {
+        let options = self.dump_options();
+        let bytes = string.as_bytes();
+        let mut buffer = [0; 12];
+        let mut start = 0;
+
+        try!(self.write_char(b'"'));
+
+        for (index, ch) in string.char_indices() {
+            if !options.escapes(ch) {
+                continue;
             }
+
+            let len = escape_char(ch, &mut buffer);
+
+            try!(self.write(&bytes[start .. index]));
+            try!(self.write(&buffer[.. len]));
+
+            start = index + ch.len_utf8();
         }
-        self.write(string[start ..].as_bytes());
 
-        self.write_char(b'"');
+        try!(self.write(&bytes[start ..]));
+        self.write_char(b'"')
     }
 
+    /// Writes the string quoted and escaped.
     #[inline(always)]
-    fn write_string(&mut self, string: &str) {
-        self.write_char(b'"');
+    fn write_string(&mut self, string: &str) -> io::Result<()> {
+        if self.dump_options().escapes_extra() {
+            return self.write_string_escaped(string);
+        }
 
-        for (index, ch) in string.bytes().enumerate() {
-            if ESCAPED[ch as usize] > 0 {
-                return self.write_string_complex(string, index)
-            }
+        try!(self.write_char(b'"'));
+
+        let index = scan::find_special(string.as_bytes());
+
+        if index < string.len() {
+            return self.write_string_complex(string, index);
         }
 
-        self.write(string.as_bytes());
-        self.write_char(b'"');
+        try!(self.write(string.as_bytes()));
+        self.write_char(b'"')
     }
 
+    /// Writes integers without a fraction, very small and very large
+    /// numbers in exponent notation, and NaN and infinity according to
+    /// `DumpOptions::non_finite`.
     #[inline(always)]
-    fn write_number(&mut self, num: f64) {
+    fn write_number(&mut self, num: f64) -> io::Result<()> {
         match num.classify() {
             FpCategory::Normal    |
             FpCategory::Subnormal => {
                 if num.fract() == 0.0 && num.abs() < 1e19 {
-                    itoa::write(self.get_writer(), num as i64).unwrap();
+                    itoa::write(self.get_writer(), num as i64)
                 } else {
                     let abs = num.abs();
                     if abs < 1e-15 || abs > 1e19 {
-                        write!(self.get_writer(), "{:e}", num).unwrap();
+                        write!(self.get_writer(), "{:e}", num)
                     } else {
-                        write!(self.get_writer(), "{}", num).unwrap();
+                        write!(self.get_writer(), "{}", num)
                     }
                 }
             },
             FpCategory::Zero => {
                 if num.is_sign_negative() {
-                    self.write(b"-0");
+                    self.write(b"-0")
                 } else {
-                    self.write_char(b'0');
+                    self.write_char(b'0')
                 }
             },
             FpCategory::Nan      |
             FpCategory::Infinite => {
-                self.write(b"null");
+                match self.dump_options().non_finite {
+                    NonFinite::Null    => self.write(b"null"),
+                    NonFinite::Error   => Err(non_finite_error()),
+                    NonFinite::Literal => {
+                        if num.is_nan() {
+                            self.write(b"NaN")
+                        } else if num.is_sign_negative() {
+                            self.write(b"-Infinity")
+                        } else {
+                            self.write(b"Infinity")
+                        }
+                    }
+                }
             }
         }
     }
 
-    fn write_json(&mut self, json: &JsonValue) {
+    #[inline(always)]
+    fn write_integer(&mut self, num: Integer) -> io::Result<()> {
+        num.write_to(self.get_writer())
+    }
+
+    /// Writes the whole value, calling the other hooks.
+    fn write_json(&mut self, json: &JsonValue) -> io::Result<()> {
         match *json {
             JsonValue::String(ref string) => self.write_string(string),
             JsonValue::Number(ref number) => self.write_number(*number),
-            JsonValue::Boolean(true)      => self.write(b"true"),
-            JsonValue::Boolean(false)     => self.write(b"false"),
-            JsonValue::Null               => self.write(b"null"),
+            JsonValue::Integer(number)    => self.write_integer(number),
+            JsonValue::Boolean(value)     => self.write_bool(value),
+            JsonValue::Null               => self.write_null(),
             JsonValue::Array(ref array)   => {
-                self.write_char(b'[');
-                let mut iter = array.iter();
+                try!(self.begin_array());
 
-                if let Some(item) = iter.next() {
-                    self.indent();
-                    self.new_line();
-                    self.write_json(item);
-                } else {
-                    self.write_char(b']');
-                    return;
-                }
-
-                for item in iter {
-                    self.write_char(b',');
-                    self.new_line();
-                    self.write_json(item);
+                for (index, item) in array.iter().enumerate() {
+                    try!(self.begin_array_value(index == 0));
+                    try!(self.write_json(item));
                 }
 
-                self.dedent();
-                self.new_line();
-                self.write_char(b']');
+                self.end_array(array.is_empty())
             },
             JsonValue::Object(ref object) => {
-                self.write_char(b'{');
-                let mut iter = object.iter();
-
-                if let Some((key, value)) = iter.next() {
-                    self.indent();
-                    self.new_line();
-                    self.write_string(key);
-                    self.write_min(b": ", b':');
-                    self.write_json(value);
-                } else {
-                    self.write_char(b'}');
-                    return;
-                }
+                try!(self.begin_object());
 
-                for (key, value) in iter {
-                    self.write_char(b',');
-                    self.new_line();
-                    self.write_string(key);
-                    self.write_min(b": ", b':');
-                    self.write_json(value);
+                for (index, (key, value)) in object.iter().enumerate() {
+                    try!(self.begin_object_key(index == 0));
+                    try!(self.write_key(key));
+                    try!(self.begin_object_value());
+                    try!(self.write_json(value));
                 }
 
-                self.dedent();
-                self.new_line();
-                self.write_char(b'}');
+                self.end_object(object.is_empty())
             }
         }
     }
 }
 
+fn into_string(code: Vec<u8>) -> String {
+    match String::from_utf8(code) {
+        Ok(string) => string,
+        Err(err)   => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    }
+}
+
+/// Writes compact JSON into a `String`, used by `JsonValue::dump`.
 pub struct DumpGenerator {
     code: Vec<u8>,
+    options: DumpOptions,
 }
 
 impl DumpGenerator {
-    pub fn new() -> Self {
+    pub fn new(options: DumpOptions) -> Self {
         DumpGenerator {
             code: Vec::with_capacity(1024),
+            options: options,
         }
     }
 
+    /// Returns the generated JSON. Bytes written with `write` that aren't
+    /// valid UTF-8 are replaced with U+FFFD.
     pub fn consume(self) -> String {
+        into_string(self.code)
+    }
+
+    // Same as `consume`, for when nothing but `write_json` was called
+    pub(crate) fn consume_json(self) -> String {
         // Original strings were unicode, numbers are all ASCII,
         // therefore this is safe.
         unsafe { String::from_utf8_unchecked(self.code) }
@@ -205,13 +608,15 @@ impl Generator for DumpGenerator {
     type T = Vec<u8>;
 
     #[inline(always)]
-    fn write(&mut self, slice: &[u8]) {
-        self.code.extend_from_slice(slice)
+    fn write(&mut self, slice: &[u8]) -> io::Result<()> {
+        self.code.extend_from_slice(slice);
+        Ok(())
     }
 
     #[inline(always)]
-    fn write_char(&mut self, ch: u8) {
-        self.code.push(ch)
+    fn write_char(&mut self, ch: u8) -> io::Result<()> {
+        self.code.push(ch);
+        Ok(())
     }
 
     #[inline(always)]
@@ -220,28 +625,275 @@ impl Generator for DumpGenerator {
     }
 
     #[inline(always)]
-    fn write_min(&mut self, _: &[u8], min: u8) {
-        self.code.push(min);
+    fn dump_options(&self) -> DumpOptions {
+        self.options
+    }
+}
+
+/// How to indent nested blocks in pretty printed JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    /// Given number of spaces per level.
+    Spaces(u16),
+
+    /// A single tab character per level.
+    Tab,
+
+    /// Don't break lines at all, print everything on a single line.
+    None,
+}
+
+/// Character sequence to end lines with in pretty printed JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+
+    /// `\r\n`
+    CrLf,
+}
+
+/// Options for pretty printing JSON with `JsonValue::pretty`,
+/// `JsonValue::to_writer_pretty` and `json::stringify_pretty`. A number of
+/// spaces converts into the default options with that indentation.
+///
+/// ```
+/// # #[macro_use] extern crate json;
+/// # fn main() {
+/// # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
+/// use json::{ PrettyOptions, Indent, LineEnding };
+///
+/// let data = object!{ "bar" => array![], "foo" => array![1, 2] };
+///
+/// assert_eq!(data.pretty(2), "{\n  \"bar\": [],\n  \"foo\": [\n    1,\n    2\n  ]\n}");
+///
+/// let options = PrettyOptions::new()
+///     .indent(Indent::Tab)
+///     .line_ending(LineEnding::CrLf)
+///     .space_after_colon(false)
+///     .trailing_newline(true);
+///
+/// assert_eq!(data.pretty(options), "{\r\n\t\"bar\":[],\r\n\t\"foo\":[\r\n\t\t1,\r\n\t\t2\r\n\t]\r\n}\r\n");
+///
+/// let options = PrettyOptions::new().indent(Indent::None);
+///
+/// assert_eq!(data.pretty(options), r#"{"bar": [], "foo": [1, 2]}"#);
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrettyOptions {
+    indent: Indent,
+    line_ending: LineEnding,
+    space_after_colon: bool,
+    space_after_comma: bool,
+    trailing_newline: bool,
+    compact_empty: bool,
+    max_width: Option<usize>,
+    dump: DumpOptions,
+}
+
+impl PrettyOptions {
+    /// Indent with 4 spaces, end lines with `\n`, put spaces after colons
+    /// and commas, print empty arrays and objects as `[]` and `{}`, don't
+    /// end the output with a new line.
+    pub fn new() -> Self {
+        PrettyOptions {
+            indent: Indent::Spaces(4),
+            line_ending: LineEnding::Lf,
+            space_after_colon: true,
+            space_after_comma: true,
+            trailing_newline: false,
+            compact_empty: true,
+            max_width: None,
+            dump: DumpOptions::new(),
+        }
+    }
+
+    pub fn indent(mut self, indent: Indent) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub fn space_after_colon(mut self, space: bool) -> Self {
+        self.space_after_colon = space;
+        self
+    }
+
+    /// Only affects commas that aren't followed by a line break.
+    pub fn space_after_comma(mut self, space: bool) -> Self {
+        self.space_after_comma = space;
+        self
+    }
+
+    pub fn trailing_newline(mut self, newline: bool) -> Self {
+        self.trailing_newline = newline;
+        self
+    }
+
+    /// With `false` empty arrays and objects are printed with a line break
+    /// between the brackets, same as non-empty ones.
+    pub fn compact_empty(mut self, compact: bool) -> Self {
+        self.compact_empty = compact;
+        self
+    }
+
+    /// Keep arrays and objects on a single line, as long as that line fits
+    /// within `width` characters, and only break them into multiple lines
+    /// otherwise. Lines can still end up longer than `width` if a single
+    /// value doesn't fit.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
+    /// use json::PrettyOptions;
+    ///
+    /// let data = object!{
+    ///     "name" => "json-rust",
+    ///     "numbers" => array![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+    ///     "point" => object!{ "x" => 10, "y" => 20 }
+    /// };
+    ///
+    /// assert_eq!(data.pretty(PrettyOptions::from(2).max_width(50)), r#"{
+    ///   "name": "json-rust",
+    ///   "numbers": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+    ///   "point": {"x": 10, "y": 20}
+    /// }"#);
+    /// # }
+    /// ```
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Same as `DumpOptions::ascii_only`.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.dump = self.dump.ascii_only(ascii_only);
+        self
+    }
+
+    /// Same as `DumpOptions::html_safe`.
+    pub fn html_safe(mut self, html_safe: bool) -> Self {
+        self.dump = self.dump.html_safe(html_safe);
+        self
+    }
+
+    /// Same as `DumpOptions::non_finite`.
+    pub fn non_finite(mut self, non_finite: NonFinite) -> Self {
+        self.dump = self.dump.non_finite(non_finite);
+        self
+    }
+
+    pub(crate) fn dump_options(&self) -> DumpOptions {
+        self.dump
+    }
+
+    // Same options, only writing `null` where `NonFinite::Error` would fail
+    pub(crate) fn infallible(self) -> Self {
+        if self.dump.rejects_non_finite() {
+            return self.non_finite(NonFinite::Null);
+        }
+
+        self
+    }
+
+    // Whether the output has to be generated with `CompactPrettyGenerator`
+    pub(crate) fn is_width_aware(&self) -> bool {
+        self.max_width.is_some()
+    }
+
+    fn line_ending_bytes(&self) -> &'static [u8] {
+        match self.line_ending {
+            LineEnding::Lf   => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions::new()
+    }
+}
+
+impl From<u16> for PrettyOptions {
+    fn from(spaces: u16) -> Self {
+        PrettyOptions::new().indent(Indent::Spaces(spaces))
+    }
+}
+
+// Shared by pretty generators writing into different outputs
+fn write_new_line<W: Write>(writer: &mut W, options: &PrettyOptions, dent: u16) -> io::Result<()> {
+    static SPACES: &[u8] = b"                                ";
+    static TABS: &[u8] = b"\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t";
+
+    let (fill, mut count) = match options.indent {
+        Indent::Spaces(spaces) => (SPACES, dent as usize * spaces as usize),
+        Indent::Tab            => (TABS, dent as usize),
+        Indent::None           => return Ok(()),
+    };
+
+    try!(writer.write_all(options.line_ending_bytes()));
+
+    while count > 0 {
+        let chunk = if count < fill.len() { count } else { fill.len() };
+        try!(writer.write_all(&fill[.. chunk]));
+        count -= chunk;
+    }
+
+    Ok(())
+}
+
+fn write_trailing_newline<W: Write>(writer: &mut W, options: &PrettyOptions) -> io::Result<()> {
+    if options.trailing_newline {
+        try!(writer.write_all(options.line_ending_bytes()));
+    }
+
+    Ok(())
+}
+
+fn write_colon<W: Write>(writer: &mut W, options: &PrettyOptions) -> io::Result<()> {
+    writer.write_all(if options.space_after_colon { b": " } else { b":" })
+}
+
+fn write_comma<W: Write>(writer: &mut W, options: &PrettyOptions) -> io::Result<()> {
+    if options.indent == Indent::None && options.space_after_comma {
+        writer.write_all(b", ")
+    } else {
+        writer.write_all(b",")
     }
 }
 
+/// Writes pretty printed JSON into a `String`, used by `JsonValue::pretty`.
 pub struct PrettyGenerator {
-    code: Vec<u8>,
-    dent: u16,
-    spaces_per_indent: u16,
+    inner: PrettyWriterGenerator<Vec<u8>>,
 }
 
 impl PrettyGenerator {
-    pub fn new(spaces: u16) -> Self {
+    pub fn new(options: PrettyOptions) -> Self {
         PrettyGenerator {
-            code: Vec::with_capacity(1024),
-            dent: 0,
-            spaces_per_indent: spaces
+            inner: PrettyWriterGenerator::new(Vec::with_capacity(1024), options),
         }
     }
 
+    pub fn write_document(&mut self, json: &JsonValue) -> io::Result<()> {
+        self.inner.write_document(json)
+    }
+
+    /// Returns the generated JSON. Bytes written with `write` that aren't
+    /// valid UTF-8 are replaced with U+FFFD.
     pub fn consume(self) -> String {
-        unsafe { String::from_utf8_unchecked(self.code) }
+        into_string(self.inner.writer)
+    }
+
+    // Same as `consume`, for when nothing but `write_document` was called
+    pub(crate) fn consume_json(self) -> String {
+        unsafe { String::from_utf8_unchecked(self.inner.writer) }
     }
 }
 
@@ -249,49 +901,69 @@ impl Generator for PrettyGenerator {
     type T = Vec<u8>;
 
     #[inline(always)]
-    fn write(&mut self, slice: &[u8]) {
-        self.code.extend_from_slice(slice)
+    fn write(&mut self, slice: &[u8]) -> io::Result<()> {
+        self.inner.writer.extend_from_slice(slice);
+        Ok(())
     }
 
     #[inline(always)]
-    fn write_char(&mut self, ch: u8) {
-        self.code.push(ch)
+    fn write_char(&mut self, ch: u8) -> io::Result<()> {
+        self.inner.writer.push(ch);
+        Ok(())
     }
 
     #[inline(always)]
     fn get_writer(&mut self) -> &mut Vec<u8> {
-        &mut self.code
+        self.inner.get_writer()
     }
 
     #[inline(always)]
-    fn write_min(&mut self, slice: &[u8], _: u8) {
-        self.code.extend_from_slice(slice);
+    fn begin_object_value(&mut self) -> io::Result<()> {
+        self.inner.begin_object_value()
     }
 
-    fn new_line(&mut self) {
-        self.code.push(b'\n');
-        for _ in 0..(self.dent * self.spaces_per_indent) {
-            self.code.push(b' ');
-        }
+    fn new_line(&mut self) -> io::Result<()> {
+        self.inner.new_line()
+    }
+
+    fn write_comma(&mut self) -> io::Result<()> {
+        self.inner.write_comma()
+    }
+
+    fn write_empty(&mut self) -> io::Result<()> {
+        self.inner.write_empty()
+    }
+
+    fn end_document(&mut self) -> io::Result<()> {
+        self.inner.end_document()
     }
 
     fn indent(&mut self) {
-        self.dent += 1;
+        self.inner.indent()
     }
 
     fn dedent(&mut self) {
-        self.dent -= 1;
+        self.inner.dedent()
+    }
+
+    #[inline(always)]
+    fn dump_options(&self) -> DumpOptions {
+        self.inner.dump_options()
     }
 }
 
+/// Writes compact JSON into an instance of `std::io::Write`, used by
+/// `JsonValue::to_writer`.
 pub struct WriterGenerator<'a, W: 'a + Write> {
-    writer: &'a mut W
+    writer: &'a mut W,
+    options: DumpOptions,
 }
 
 impl<'a, W> WriterGenerator<'a, W> where W: 'a + Write {
-    pub fn new(writer: &'a mut W) -> Self {
+    pub fn new(writer: &'a mut W, options: DumpOptions) -> Self {
         WriterGenerator {
-            writer: writer
+            writer: writer,
+            options: options,
         }
     }
 }
@@ -305,7 +977,794 @@ impl<'a, W> Generator for WriterGenerator<'a, W> where W: Write {
     }
 
     #[inline(always)]
-    fn write_min(&mut self, _: &[u8], min: u8) {
-        self.writer.write_all(&[min]).unwrap();
+    fn dump_options(&self) -> DumpOptions {
+        self.options
+    }
+}
+
+/// Writes pretty printed JSON into an instance of `std::io::Write`, used by
+/// `JsonValue::to_writer_pretty`.
+pub struct PrettyWriterGenerator<W: Write> {
+    writer: W,
+    dent: u16,
+    options: PrettyOptions,
+}
+
+impl<W> PrettyWriterGenerator<W> where W: Write {
+    pub fn new(writer: W, options: PrettyOptions) -> Self {
+        PrettyWriterGenerator::at_depth(writer, options, 0)
+    }
+
+    /// Continues output that is already nested `dent` levels deep.
+    pub fn at_depth(writer: W, options: PrettyOptions, dent: u16) -> Self {
+        PrettyWriterGenerator {
+            writer: writer,
+            dent: dent,
+            options: options,
+        }
+    }
+
+    pub fn write_document(&mut self, json: &JsonValue) -> io::Result<()> {
+        try!(self.write_json(json));
+        self.end_document()
+    }
+}
+
+impl<W> Generator for PrettyWriterGenerator<W> where W: Write {
+    type T = W;
+
+    #[inline(always)]
+    fn get_writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    #[inline(always)]
+    fn begin_object_value(&mut self) -> io::Result<()> {
+        write_colon(&mut self.writer, &self.options)
+    }
+
+    fn new_line(&mut self) -> io::Result<()> {
+        write_new_line(&mut self.writer, &self.options, self.dent)
+    }
+
+    fn write_comma(&mut self) -> io::Result<()> {
+        write_comma(&mut self.writer, &self.options)
+    }
+
+    fn write_empty(&mut self) -> io::Result<()> {
+        if self.options.compact_empty {
+            return Ok(());
+        }
+
+        self.new_line()
+    }
+
+    fn end_document(&mut self) -> io::Result<()> {
+        write_trailing_newline(&mut self.writer, &self.options)
+    }
+
+    fn indent(&mut self) {
+        self.dent += 1;
+    }
+
+    fn dedent(&mut self) {
+        self.dent -= 1;
+    }
+
+    #[inline(always)]
+    fn dump_options(&self) -> DumpOptions {
+        self.options.dump
+    }
+}
+
+/// Color of a kind of token in a `Theme`. Written out as an ANSI escape
+/// code setting the foreground color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Leave the token as it is, without any escape codes.
+    Plain,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+
+    /// One of the 256 colors of the extended palette, `8` to `15` are the
+    /// bright variants of the basic colors.
+    Fixed(u8),
+
+    /// 24-bit color, not supported by every terminal.
+    Rgb(u8, u8, u8),
+}
+
+/// Colors used by `JsonValue::to_writer_colored` and
+/// `JsonValue::to_terminal` for the different kinds of tokens.
+///
+/// ```
+/// # #[macro_use] extern crate json;
+/// # fn main() {
+/// use json::{ Theme, Color };
+///
+/// let data = object!{ "on" => true };
+/// let theme = Theme::plain().key(Color::Red);
+///
+/// let mut buf = Vec::new();
+/// data.to_writer_colored(&mut buf, 2, theme).unwrap();
+///
+/// assert_eq!(String::from_utf8(buf).unwrap(), "{\n  \x1b[31m\"on\"\x1b[0m: true\n}");
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    key: Color,
+    string: Color,
+    number: Color,
+    boolean: Color,
+    null: Color,
+    punctuation: Color,
+}
+
+impl Theme {
+    /// Blue keys, green strings, cyan numbers, yellow booleans, gray `null`
+    /// and plain brackets, commas and colons.
+    pub fn new() -> Self {
+        Theme {
+            key: Color::Blue,
+            string: Color::Green,
+            number: Color::Cyan,
+            boolean: Color::Yellow,
+            null: Color::Fixed(8),
+            punctuation: Color::Plain,
+        }
+    }
+
+    /// No colors at all.
+    pub fn plain() -> Self {
+        Theme {
+            key: Color::Plain,
+            string: Color::Plain,
+            number: Color::Plain,
+            boolean: Color::Plain,
+            null: Color::Plain,
+            punctuation: Color::Plain,
+        }
+    }
+
+    pub fn key(mut self, color: Color) -> Self {
+        self.key = color;
+        self
+    }
+
+    pub fn string(mut self, color: Color) -> Self {
+        self.string = color;
+        self
+    }
+
+    pub fn number(mut self, color: Color) -> Self {
+        self.number = color;
+        self
+    }
+
+    pub fn boolean(mut self, color: Color) -> Self {
+        self.boolean = color;
+        self
+    }
+
+    pub fn null(mut self, color: Color) -> Self {
+        self.null = color;
+        self
+    }
+
+    /// Color of brackets, braces, commas and colons.
+    pub fn punctuation(mut self, color: Color) -> Self {
+        self.punctuation = color;
+        self
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new()
+    }
+}
+
+fn write_color<W: Write>(writer: &mut W, color: Color) -> io::Result<()> {
+    let code: &[u8] = match color {
+        Color::Plain        => return Ok(()),
+        Color::Black        => b"\x1b[30m",
+        Color::Red          => b"\x1b[31m",
+        Color::Green        => b"\x1b[32m",
+        Color::Yellow       => b"\x1b[33m",
+        Color::Blue         => b"\x1b[34m",
+        Color::Magenta      => b"\x1b[35m",
+        Color::Cyan         => b"\x1b[36m",
+        Color::White        => b"\x1b[37m",
+        Color::Fixed(index) => return write!(writer, "\x1b[38;5;{}m", index),
+        Color::Rgb(r, g, b) => return write!(writer, "\x1b[38;2;{};{};{}m", r, g, b),
+    };
+
+    writer.write_all(code)
+}
+
+fn write_reset<W: Write>(writer: &mut W, color: Color) -> io::Result<()> {
+    if color == Color::Plain {
+        return Ok(());
+    }
+
+    writer.write_all(b"\x1b[0m")
+}
+
+/// Pretty printer that highlights the syntax with ANSI escape codes, used by
+/// `JsonValue::to_writer_colored`. Every token is colored separately, so
+/// the output can be cut at line breaks without colors leaking. Doesn't
+/// take `PrettyOptions::max_width` into account.
+pub struct ColorGenerator<'a, W: 'a + Write> {
+    writer: &'a mut W,
+    dent: u16,
+    options: PrettyOptions,
+    theme: Theme,
+}
+
+impl<'a, W> ColorGenerator<'a, W> where W: 'a + Write {
+    pub fn new(writer: &'a mut W, options: PrettyOptions, theme: Theme) -> Self {
+        ColorGenerator {
+            writer: writer,
+            dent: 0,
+            options: options,
+            theme: theme,
+        }
+    }
+
+    pub fn write_document(&mut self, json: &JsonValue) -> io::Result<()> {
+        try!(self.write_json(json));
+        self.end_document()
+    }
+
+    fn paint(&mut self, color: Color, token: &[u8]) -> io::Result<()> {
+        try!(write_color(self.writer, color));
+        try!(self.writer.write_all(token));
+        write_reset(self.writer, color)
+    }
+
+    // Writes scalars with the uncolored generator, wrapped in `color`
+    fn paint_with<F>(&mut self, color: Color, write: F) -> io::Result<()>
+    where F: FnOnce(&mut WriterGenerator<W>) -> io::Result<()> {
+        try!(write_color(self.writer, color));
+        try!(write(&mut WriterGenerator::new(self.writer, self.options.dump)));
+        write_reset(self.writer, color)
+    }
+
+    fn close(&mut self, empty: bool, bracket: &[u8]) -> io::Result<()> {
+        if empty {
+            try!(self.write_empty());
+        } else {
+            self.dedent();
+            try!(self.new_line());
+        }
+
+        let color = self.theme.punctuation;
+        self.paint(color, bracket)
+    }
+}
+
+impl<'a, W> Generator for ColorGenerator<'a, W> where W: Write {
+    type T = W;
+
+    #[inline(always)]
+    fn get_writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    fn new_line(&mut self) -> io::Result<()> {
+        write_new_line(self.writer, &self.options, self.dent)
+    }
+
+    fn write_comma(&mut self) -> io::Result<()> {
+        let color = self.theme.punctuation;
+        try!(self.paint(color, b","));
+
+        if self.options.indent == Indent::None && self.options.space_after_comma {
+            try!(self.writer.write_all(b" "));
+        }
+
+        Ok(())
+    }
+
+    fn write_empty(&mut self) -> io::Result<()> {
+        if self.options.compact_empty {
+            return Ok(());
+        }
+
+        self.new_line()
+    }
+
+    fn end_document(&mut self) -> io::Result<()> {
+        write_trailing_newline(self.writer, &self.options)
+    }
+
+    fn indent(&mut self) {
+        self.dent += 1;
+    }
+
+    fn dedent(&mut self) {
+        self.dent -= 1;
+    }
+
+    #[inline(always)]
+    fn dump_options(&self) -> DumpOptions {
+        self.options.dump
+    }
+
+    fn begin_array(&mut self) -> io::Result<()> {
+        let color = self.theme.punctuation;
+        self.paint(color, b"[")
+    }
+
+    fn end_array(&mut self, empty: bool) -> io::Result<()> {
+        self.close(empty, b"]")
+    }
+
+    fn begin_object(&mut self) -> io::Result<()> {
+        let color = self.theme.punctuation;
+        self.paint(color, b"{")
+    }
+
+    fn end_object(&mut self, empty: bool) -> io::Result<()> {
+        self.close(empty, b"}")
+    }
+
+    fn write_key(&mut self, key: &str) -> io::Result<()> {
+        let color = self.theme.key;
+        self.paint_with(color, |gen| gen.write_string(key))
+    }
+
+    fn begin_object_value(&mut self) -> io::Result<()> {
+        let color = self.theme.punctuation;
+        try!(self.paint(color, b":"));
+
+        if self.options.space_after_colon {
+            try!(self.writer.write_all(b" "));
+        }
+
+        Ok(())
+    }
+
+    fn write_string(&mut self, string: &str) -> io::Result<()> {
+        let color = self.theme.string;
+        self.paint_with(color, |gen| gen.write_string(string))
+    }
+
+    fn write_number(&mut self, num: f64) -> io::Result<()> {
+        let color = self.theme.number;
+        self.paint_with(color, |gen| gen.write_number(num))
+    }
+
+    fn write_integer(&mut self, num: Integer) -> io::Result<()> {
+        let color = self.theme.number;
+        self.paint_with(color, |gen| gen.write_integer(num))
+    }
+
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        let color = self.theme.boolean;
+        let literal: &[u8] = if value { b"true" } else { b"false" };
+        self.paint(color, literal)
+    }
+
+    fn write_null(&mut self) -> io::Result<()> {
+        let color = self.theme.null;
+        self.paint(color, b"null")
+    }
+}
+
+// Tabs are assumed to take this many columns when checking line width
+const TAB_WIDTH: usize = 4;
+
+macro_rules! try_opt {
+    ($value:expr) => {
+        match $value {
+            Some(value) => value,
+            None        => return None,
+        }
+    }
+}
+
+// Number of characters the string takes once quoted and escaped, or `None`
+// if it's more than `budget`.
+fn string_width(string: &str, options: &DumpOptions, budget: usize) -> Option<usize> {
+    let mut width = 2;
+
+    for ch in string.chars() {
+        width += if options.escapes(ch) { escape_len(ch) } else { 1 };
+
+        if width > budget {
+            return None;
+        }
+    }
+
+    Some(width)
+}
+
+// Holds a single number rendered by `write_number` or `write_integer`, which
+// is never longer than 40 bytes, without allocating.
+struct NumberBuffer {
+    bytes: [u8; 64],
+    len: usize,
+}
+
+impl NumberBuffer {
+    fn new() -> Self {
+        NumberBuffer {
+            bytes: [0; 64],
+            len: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[.. self.len]
+    }
+}
+
+impl Write for NumberBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let free = &mut self.bytes[self.len ..];
+        let len = if buf.len() < free.len() { buf.len() } else { free.len() };
+
+        free[.. len].copy_from_slice(&buf[.. len]);
+        self.len += len;
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Pretty printer that keeps arrays and objects on a single line when they
+/// fit within `PrettyOptions::max_width`, and breaks them into lines
+/// otherwise. Only `write_document` takes the width into account.
+pub struct CompactPrettyGenerator<'a, W: 'a + Write> {
+    writer: &'a mut W,
+    dent: u16,
+    column: usize,
+    max_width: usize,
+    options: PrettyOptions,
+
+    // Numbers are rendered here first to find out how long they are
+    number: NumberBuffer,
+}
+
+impl<'a, W> CompactPrettyGenerator<'a, W> where W: 'a + Write {
+    pub fn new(writer: &'a mut W, options: PrettyOptions) -> Self {
+        CompactPrettyGenerator {
+            writer: writer,
+            dent: 0,
+            column: 0,
+            max_width: options.max_width.unwrap_or(0),
+            options: options,
+            number: NumberBuffer::new(),
+        }
+    }
+
+    pub fn write_document(&mut self, json: &JsonValue) -> io::Result<()> {
+        try!(self.write_value(json, 0));
+        self.end_document()
+    }
+
+    fn render_number(&mut self, num: f64) -> io::Result<()> {
+        self.number.clear();
+        WriterGenerator::new(&mut self.number, self.options.dump).write_number(num)
+    }
+
+    fn render_integer(&mut self, num: Integer) -> io::Result<()> {
+        self.number.clear();
+        num.write_to(&mut self.number)
+    }
+
+    fn comma_width(&self) -> usize {
+        if self.options.space_after_comma { 2 } else { 1 }
+    }
+
+    fn colon_width(&self) -> usize {
+        if self.options.space_after_colon { 2 } else { 1 }
+    }
+
+    // Width of the value written out on a single line, or `None` if it
+    // exceeds the `budget`.
+    fn measure(&mut self, json: &JsonValue, budget: usize) -> Option<usize> {
+        let width = match *json {
+            JsonValue::String(ref string) => try_opt!(string_width(string, &self.options.dump, budget)),
+            JsonValue::Number(number) => {
+                // The error comes up again once the number is written
+                try_opt!(self.render_number(number).ok());
+                self.number.len
+            },
+            JsonValue::Integer(number) => {
+                try_opt!(self.render_integer(number).ok());
+                self.number.len
+            },
+            JsonValue::Boolean(true)  => 4,
+            JsonValue::Boolean(false) => 5,
+            JsonValue::Null           => 4,
+            JsonValue::Array(ref array) => {
+                if array.is_empty() && !self.options.compact_empty {
+                    return None;
+                }
+
+                let mut width = 2;
+
+                for (index, item) in array.iter().enumerate() {
+                    if index > 0 {
+                        width += self.comma_width();
+                    }
+
+                    width += try_opt!(self.measure(item, budget.saturating_sub(width)));
+                }
+
+                width
+            },
+            JsonValue::Object(ref object) => {
+                if object.is_empty() && !self.options.compact_empty {
+                    return None;
+                }
+
+                let mut width = 2;
+
+                for (index, (key, value)) in object.iter().enumerate() {
+                    if index > 0 {
+                        width += self.comma_width();
+                    }
+
+                    width += try_opt!(string_width(key, &self.options.dump, budget.saturating_sub(width)));
+                    width += self.colon_width();
+                    width += try_opt!(self.measure(value, budget.saturating_sub(width)));
+                }
+
+                width
+            }
+        };
+
+        if width > budget {
+            None
+        } else {
+            Some(width)
+        }
+    }
+
+    // `trailing` is the number of characters that follow the value on the
+    // same line, ie: a comma.
+    fn write_value(&mut self, json: &JsonValue, trailing: usize) -> io::Result<()> {
+        match *json {
+            JsonValue::Array(ref array) if !array.is_empty() => {},
+            JsonValue::Object(ref object) if !object.is_empty() => {},
+            _ => return self.write_json(json),
+        }
+
+        let budget = self.max_width.saturating_sub(self.column + trailing);
+
+        if self.measure(json, budget).is_some() {
+            return self.write_inline(json);
+        }
+
+        match *json {
+            JsonValue::Array(ref array) => {
+                try!(self.write_char(b'['));
+                self.indent();
+
+                for (index, item) in array.iter().enumerate() {
+                    let last = index + 1 == array.len();
+
+                    try!(self.new_line());
+                    try!(self.write_value(item, if last { 0 } else { 1 }));
+
+                    if !last {
+                        try!(self.write_char(b','));
+                    }
+                }
+
+                self.dedent();
+                try!(self.new_line());
+                self.write_char(b']')
+            },
+            JsonValue::Object(ref object) => {
+                try!(self.write_char(b'{'));
+                self.indent();
+
+                for (index, (key, value)) in object.iter().enumerate() {
+                    let last = index + 1 == object.len();
+
+                    try!(self.new_line());
+                    try!(self.write_string(key));
+                    try!(self.begin_object_value());
+                    try!(self.write_value(value, if last { 0 } else { 1 }));
+
+                    if !last {
+                        try!(self.write_char(b','));
+                    }
+                }
+
+                self.dedent();
+                try!(self.new_line());
+                self.write_char(b'}')
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn write_inline(&mut self, json: &JsonValue) -> io::Result<()> {
+        let comma: &[u8] = if self.options.space_after_comma { b", " } else { b"," };
+
+        match *json {
+            JsonValue::Array(ref array) => {
+                try!(self.write_char(b'['));
+
+                for (index, item) in array.iter().enumerate() {
+                    if index > 0 {
+                        try!(self.write(comma));
+                    }
+                    try!(self.write_inline(item));
+                }
+
+                self.write_char(b']')
+            },
+            JsonValue::Object(ref object) => {
+                try!(self.write_char(b'{'));
+
+                for (index, (key, value)) in object.iter().enumerate() {
+                    if index > 0 {
+                        try!(self.write(comma));
+                    }
+                    try!(self.write_string(key));
+                    try!(self.begin_object_value());
+                    try!(self.write_inline(value));
+                }
+
+                self.write_char(b'}')
+            },
+            _ => self.write_json(json)
+        }
+    }
+}
+
+impl<'a, W> Generator for CompactPrettyGenerator<'a, W> where W: Write {
+    type T = W;
+
+    #[inline(always)]
+    fn get_writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    fn write(&mut self, slice: &[u8]) -> io::Result<()> {
+        // Count characters, not bytes
+        self.column += slice.iter().filter(|&&ch| ch & 0xC0 != 0x80).count();
+        self.writer.write_all(slice)
+    }
+
+    fn write_char(&mut self, ch: u8) -> io::Result<()> {
+        self.column += 1;
+        self.writer.write_all(&[ch])
+    }
+
+    fn write_number(&mut self, num: f64) -> io::Result<()> {
+        try!(self.render_number(num));
+        self.column += self.number.len;
+        self.writer.write_all(self.number.as_bytes())
+    }
+
+    fn write_integer(&mut self, num: Integer) -> io::Result<()> {
+        try!(self.render_integer(num));
+        self.column += self.number.len;
+        self.writer.write_all(self.number.as_bytes())
+    }
+
+    fn begin_object_value(&mut self) -> io::Result<()> {
+        self.column += self.colon_width();
+        write_colon(self.writer, &self.options)
+    }
+
+    fn new_line(&mut self) -> io::Result<()> {
+        self.column = match self.options.indent {
+            Indent::Spaces(spaces) => self.dent as usize * spaces as usize,
+            Indent::Tab            => self.dent as usize * TAB_WIDTH,
+            Indent::None           => return Ok(()),
+        };
+
+        write_new_line(self.writer, &self.options, self.dent)
+    }
+
+    fn write_empty(&mut self) -> io::Result<()> {
+        if self.options.compact_empty {
+            return Ok(());
+        }
+
+        self.new_line()
+    }
+
+    fn end_document(&mut self) -> io::Result<()> {
+        write_trailing_newline(self.writer, &self.options)
+    }
+
+    fn indent(&mut self) {
+        self.dent += 1;
+    }
+
+    fn dedent(&mut self) {
+        self.dent -= 1;
+    }
+
+    #[inline(always)]
+    fn dump_options(&self) -> DumpOptions {
+        self.options.dump
+    }
+}
+
+// Discards everything written to it, only counting the bytes. Used to find
+// out the length of the output without building it.
+pub(crate) struct ByteCounter {
+    pub count: usize,
+}
+
+impl Write for ByteCounter {
+    #[inline(always)]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len();
+        Ok(buf.len())
+    }
+
+    #[inline(always)]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.count += buf.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Adapts an instance of `fmt::Write`, such as `fmt::Formatter` or `String`,
+// so that the writer generators can output to it directly.
+//
+// The generators only ever write whole UTF-8 sequences at once: strings are
+// split on ASCII characters that need escaping, everything else is ASCII.
+// Nothing enforces that for every generator, so each write is still checked,
+// and one splitting a sequence fails with `InvalidData` instead.
+pub(crate) struct FmtWriter<'a, W: 'a + fmt::Write + ?Sized> {
+    writer: &'a mut W
+}
+
+impl<'a, W> FmtWriter<'a, W> where W: 'a + fmt::Write + ?Sized {
+    pub fn new(writer: &'a mut W) -> Self {
+        FmtWriter {
+            writer: writer
+        }
+    }
+}
+
+impl<'a, W> Write for FmtWriter<'a, W> where W: fmt::Write + ?Sized {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.write_all(buf));
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let string = try!(str::from_utf8(buf).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }));
+
+        self.writer.write_str(string).map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "formatter error")
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
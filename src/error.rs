@@ -1,25 +1,306 @@
 use std::error::Error;
-use std::fmt;
+use std::sync::Arc;
+use std::{ fmt, io };
 use std::char;
 
-#[derive(Debug, PartialEq)]
+/// Broad category of a `JsonError`, allows handling errors without having
+/// to match on every single variant.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ErrorKind {
+    /// The source isn't valid JSON, `JsonWriter` was asked to write
+    /// something that isn't, or a JSON Pointer, JSONPath query or JSON
+    /// Patch is malformed.
+    Syntax,
+
+    /// The source contains bytes or escape sequences that don't form valid
+    /// UTF-8.
+    Encoding,
+
+    /// The source exceeds a limit imposed by the parser.
+    Limit,
+
+    /// A `JsonValue` was used as a type it doesn't have, or holds a value
+    /// that can't be used the way it was asked to.
+    Type,
+
+    /// Reading or writing failed on the underlying I/O.
+    Io,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
 pub enum JsonError {
+    /// The parser has run into a character it didn't expect. The `offset`
+    /// is the byte index of the character in the source, `path` is the
+    /// normalized JSONPath (ie: `$['foo'][2]`) of the value that was being
+    /// parsed, and `expected` lists the tokens that would be valid instead.
     UnexpectedCharacter {
         ch: char,
         line: usize,
         column: usize,
+        offset: usize,
+        path: String,
+        expected: &'static [&'static str],
+    },
+
+    /// The source has ended before the JSON was complete. Position fields
+    /// point at the very end of the source.
+    UnexpectedEndOfJson {
+        line: usize,
+        column: usize,
+        offset: usize,
+        path: String,
+        expected: &'static [&'static str],
     },
-    UnexpectedEndOfJson,
     FailedUtf8Parsing,
+
+    /// Arrays and objects in the source are nested deeper than
+    /// `ParseOptions::max_depth` allows.
+    ExceededDepthLimit,
     ArrayIndexOutOfBounds,
     WrongType(String),
     UndefinedField(String),
+    Io(IoError),
+
+    /// A NaN or infinite number was written out with `NonFinite::Error`.
+    NonFiniteNumber,
+
+    /// `JsonWriter` methods were called in an order that doesn't produce
+    /// valid JSON, ie: a key outside of an object.
+    InvalidWrite(&'static str),
+
+    /// A number with a fraction was read as an integer. `number` is the
+    /// number as written in JSON, `target` the name of the integer type.
+    FractionalNumber {
+        number: String,
+        target: &'static str,
+    },
+
+    /// A number was read as an integer type it doesn't fit in. `number` is
+    /// the number as written in JSON, `target` the name of the integer type.
+    NumberOutOfRange {
+        number: String,
+        target: &'static str,
+    },
+
+    /// A string used as a JSON Pointer doesn't start with `/`, or has a `~`
+    /// that isn't followed by `0` or `1`.
+    InvalidPointer(String),
+
+    /// A JSON Pointer doesn't lead to a value. `segment` is the unescaped
+    /// reference token of `pointer` that couldn't be followed.
+    PointerNotFound {
+        pointer: String,
+        segment: String,
+    },
+
+    /// A JSONPath query couldn't be compiled. `offset` is the byte index in
+    /// `query` the error was found at.
+    InvalidQuery {
+        query: String,
+        offset: usize,
+        reason: &'static str,
+    },
+
+    /// An operation of a JSON Patch failed, so none of the patch was
+    /// applied. `index` is the position of the operation in the patch, `op`
+    /// its name, `None` if it doesn't have a valid one, and `reason` the
+    /// error it failed with. The kind of the error is the kind of `reason`.
+    PatchFailed {
+        index: usize,
+        op: Option<String>,
+        reason: Box<JsonError>,
+    },
+
+    /// A JSON Patch, or one of its operations, isn't structured the way
+    /// RFC 6902 requires.
+    InvalidPatch(&'static str),
+
+    /// A member of a JSON Patch operation, such as `path`, is missing or
+    /// isn't a string.
+    InvalidPatchMember {
+        member: &'static str,
+        reason: &'static str,
+    },
+
+    /// The `test` operation of a JSON Patch found a different value at the
+    /// path.
+    TestFailed(String),
+}
+
+/// An `io::Error` that can be cloned and compared, so that `JsonError`
+/// can be. Two `IoError`s are equal when they are of the same
+/// `io::ErrorKind`.
+#[derive(Debug, Clone)]
+pub struct IoError(Arc<io::Error>);
+
+impl IoError {
+    pub fn kind(&self) -> io::ErrorKind {
+        self.0.kind()
+    }
+
+    pub fn get_ref(&self) -> &io::Error {
+        &self.0
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &IoError) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl Eq for IoError {}
+
+impl From<io::Error> for JsonError {
+    fn from(err: io::Error) -> JsonError {
+        // Generators report their own errors wrapped in an `io::Error`
+        if let Some(json) = err.get_ref().and_then(|inner| inner.downcast_ref::<JsonError>()) {
+            return json.clone();
+        }
+
+        JsonError::Io(IoError(Arc::new(err)))
+    }
 }
 
 impl JsonError {
     pub fn wrong_type(expected: &str) -> Self {
         JsonError::WrongType(expected.into())
     }
+
+    /// Category of the error.
+    pub fn kind(&self) -> ErrorKind {
+        use JsonError::*;
+
+        match *self {
+            UnexpectedCharacter { .. } => ErrorKind::Syntax,
+            UnexpectedEndOfJson { .. } => ErrorKind::Syntax,
+            FailedUtf8Parsing          => ErrorKind::Encoding,
+            ExceededDepthLimit         => ErrorKind::Limit,
+            ArrayIndexOutOfBounds      => ErrorKind::Type,
+            WrongType(_)               => ErrorKind::Type,
+            UndefinedField(_)          => ErrorKind::Type,
+            Io(_)                      => ErrorKind::Io,
+            NonFiniteNumber            => ErrorKind::Type,
+            InvalidWrite(_)            => ErrorKind::Syntax,
+            FractionalNumber { .. }    => ErrorKind::Type,
+            NumberOutOfRange { .. }    => ErrorKind::Type,
+            InvalidPointer(_)          => ErrorKind::Syntax,
+            PointerNotFound { .. }     => ErrorKind::Type,
+            InvalidQuery { .. }        => ErrorKind::Syntax,
+            PatchFailed { ref reason, .. } => reason.kind(),
+            InvalidPatch(_)            => ErrorKind::Syntax,
+            InvalidPatchMember { .. }  => ErrorKind::Syntax,
+            TestFailed(_)              => ErrorKind::Type,
+        }
+    }
+
+    /// Machine readable code of the error. Codes are stable, they will not
+    /// change between versions of the crate, and are safe to store or send
+    /// over the wire.
+    ///
+    /// ```
+    /// let err = json::parse("[1, 2").unwrap_err();
+    ///
+    /// assert_eq!(err.code(), "unexpected_end_of_json");
+    /// assert_eq!(err.kind(), json::ErrorKind::Syntax);
+    /// ```
+    pub fn code(&self) -> &'static str {
+        use JsonError::*;
+
+        match *self {
+            UnexpectedCharacter { .. } => "unexpected_character",
+            UnexpectedEndOfJson { .. } => "unexpected_end_of_json",
+            FailedUtf8Parsing          => "failed_utf8_parsing",
+            ExceededDepthLimit         => "exceeded_depth_limit",
+            ArrayIndexOutOfBounds      => "array_index_out_of_bounds",
+            WrongType(_)               => "wrong_type",
+            UndefinedField(_)          => "undefined_field",
+            Io(_)                      => "io",
+            NonFiniteNumber            => "non_finite_number",
+            InvalidWrite(_)            => "invalid_write",
+            FractionalNumber { .. }    => "fractional_number",
+            NumberOutOfRange { .. }    => "number_out_of_range",
+            InvalidPointer(_)          => "invalid_pointer",
+            PointerNotFound { .. }     => "pointer_not_found",
+            InvalidQuery { .. }        => "invalid_query",
+            PatchFailed { .. }         => "patch_failed",
+            InvalidPatch(_)            => "invalid_patch",
+            InvalidPatchMember { .. }  => "invalid_patch_member",
+            TestFailed(_)              => "test_failed",
+        }
+    }
+
+    /// Renders the line of `source` the error points at, with a caret
+    /// marking the position of the error underneath. Returns `None` if the
+    /// error doesn't come from parsing, or if `source` isn't the string
+    /// that produced it.
+    ///
+    /// ```
+    /// let source = "[\n    1,\n    x\n]";
+    /// let err = json::parse(source).unwrap_err();
+    ///
+    /// assert_eq!(err.snippet(source).unwrap(), "3 |     x\n  |     ^");
+    /// ```
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let (line, offset) = match *self {
+            JsonError::UnexpectedCharacter { line, offset, .. } |
+            JsonError::UnexpectedEndOfJson { line, offset, .. } => (line, offset),
+            _ => return None
+        };
+
+        if offset > source.len() || !source.is_char_boundary(offset) {
+            return None;
+        }
+
+        let start = source[.. offset].rfind('\n').map_or(0, |index| index + 1);
+        let end = source[offset ..].find('\n').map_or(source.len(), |index| offset + index);
+        let text = source[start .. end].trim_end_matches('\r');
+
+        let gutter = line.to_string();
+        let mut snippet = String::with_capacity(2 * (gutter.len() + text.len() + 4));
+
+        snippet.push_str(&gutter);
+        snippet.push_str(" | ");
+        snippet.push_str(text);
+        snippet.push('\n');
+
+        for _ in 0..gutter.len() {
+            snippet.push(' ');
+        }
+        snippet.push_str(" | ");
+
+        // Keep tabs so that the caret lines up with the text above it
+        for ch in source[start .. offset].chars() {
+            snippet.push(if ch == '\t' { '\t' } else { ' ' });
+        }
+        snippet.push('^');
+
+        Some(snippet)
+    }
+}
+
+// Appends a key to a normalized JSONPath, as defined in RFC 9535.
+pub fn push_path_key(path: &mut String, key: &str) {
+    path.push_str("['");
+
+    for ch in key.chars() {
+        match ch {
+            '\'' => path.push_str("\\'"),
+            '\\' => path.push_str("\\\\"),
+            '\u{8}' => path.push_str("\\b"),
+            '\u{c}' => path.push_str("\\f"),
+            '\n' => path.push_str("\\n"),
+            '\r' => path.push_str("\\r"),
+            '\t' => path.push_str("\\t"),
+            '\u{0}' ... '\u{1f}' => {
+                path.push_str(&format!("\\u{:04x}", ch as u32));
+            },
+            _ => path.push(ch),
+        }
+    }
+
+    path.push_str("']");
 }
 
 impl fmt::Display for JsonError {
@@ -31,13 +312,67 @@ impl fmt::Display for JsonError {
                 ref ch,
                 ref line,
                 ref column,
+                ..
             } => write!(f, "Unexpected character: {} at ({}:{})", ch, line, column),
 
-            UnexpectedEndOfJson   => write!(f, "Unexpected end of JSON"),
+            UnexpectedEndOfJson {
+                ref line,
+                ref column,
+                ..
+            } => write!(f, "Unexpected end of JSON at ({}:{})", line, column),
+
             FailedUtf8Parsing     => write!(f, "Failed to parse UTF-8 bytes"),
+            ExceededDepthLimit    => write!(f, "Exceeded depth limit"),
             ArrayIndexOutOfBounds => write!(f, "Array index out of bounds!"),
             WrongType(ref s)      => write!(f, "Wrong type, expected: {}", s),
-            UndefinedField(ref s) => write!(f, "Undefined field: {}", s)
+            UndefinedField(ref s) => write!(f, "Undefined field: {}", s),
+            Io(ref err)           => write!(f, "I/O error: {}", err.get_ref()),
+            NonFiniteNumber       => write!(f, "Can't write NaN or infinite number as JSON"),
+            InvalidWrite(s)       => write!(f, "Invalid write: {}", s),
+
+            FractionalNumber {
+                ref number,
+                target,
+            } => write!(f, "Number {} is not an integer, expected: {}", number, target),
+
+            NumberOutOfRange {
+                ref number,
+                target,
+            } => write!(f, "Number {} is out of range of {}", number, target),
+
+            InvalidPointer(ref s) => write!(f, "Invalid JSON Pointer: {}", s),
+
+            PointerNotFound {
+                ref pointer,
+                ref segment,
+            } => write!(f, "JSON Pointer {} not found, no value at: {}", pointer, segment),
+
+            InvalidQuery {
+                offset,
+                reason,
+                ..
+            } => write!(f, "Invalid JSONPath query at offset {}: {}", offset, reason),
+
+            PatchFailed {
+                index,
+                op: Some(ref op),
+                ref reason,
+            } => write!(f, "JSON Patch operation {} ({}) failed: {}", index, op, reason),
+
+            PatchFailed {
+                index,
+                op: None,
+                ref reason,
+            } => write!(f, "JSON Patch operation {} failed: {}", index, reason),
+
+            InvalidPatch(s)       => write!(f, "Invalid JSON Patch: {}", s),
+
+            InvalidPatchMember {
+                member,
+                reason,
+            } => write!(f, "Invalid JSON Patch: `{}` {}", member, reason),
+
+            TestFailed(ref s)     => write!(f, "Test failed, different value at: {}", s),
         }
     }
 }
@@ -47,11 +382,32 @@ impl Error for JsonError {
         use JsonError::*;
         match *self {
             UnexpectedCharacter { .. } => "Unexpected character",
-            UnexpectedEndOfJson        => "Unexpected end of JSON",
+            UnexpectedEndOfJson { .. } => "Unexpected end of JSON",
             FailedUtf8Parsing          => "Failed to read bytes as UTF-8 from JSON",
+            ExceededDepthLimit         => "Exceeded depth limit",
             ArrayIndexOutOfBounds      => "Array index out of bounds!",
             WrongType(_)               => "Wrong type",
             UndefinedField(_)          => "Undefined field",
+            Io(_)                      => "I/O error",
+            NonFiniteNumber            => "NaN or infinite number",
+            InvalidWrite(_)            => "Invalid write",
+            FractionalNumber { .. }    => "Number is not an integer",
+            NumberOutOfRange { .. }    => "Number out of range",
+            InvalidPointer(_)          => "Invalid JSON Pointer",
+            PointerNotFound { .. }     => "JSON Pointer not found",
+            InvalidQuery { .. }        => "Invalid JSONPath query",
+            PatchFailed { .. }         => "JSON Patch operation failed",
+            InvalidPatch(_)            => "Invalid JSON Patch",
+            InvalidPatchMember { .. }  => "Invalid JSON Patch member",
+            TestFailed(_)              => "Test failed",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            JsonError::Io(ref err)                    => Some(err.get_ref()),
+            JsonError::PatchFailed { ref reason, .. } => Some(&**reason),
+            _                                         => None,
         }
     }
 }
@@ -0,0 +1,243 @@
+use std::io::{ self, Write };
+use std::cmp::Ordering;
+use std::{ fmt, i64, u64, i128 };
+
+extern crate itoa;
+
+/// Integer stored in `JsonValue::Integer`, exactly, without going through
+/// `f64`. Holds anything from `i128::MIN` up to `u128::MAX`.
+///
+/// ```
+/// let data = json::parse(r#"{ "id": 9007199254740993 }"#).unwrap();
+///
+/// assert!(data["id"].is_integer());
+/// assert!(data["id"] == 9007199254740993u64);
+/// assert!(data["id"] != 9007199254740992u64);
+/// assert_eq!(data.dump(), r#"{"id":9007199254740993}"#);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Integer {
+    repr: Repr,
+}
+
+// Every value has exactly one representation, the first one it fits in.
+// 128-bit integers are split into halves, which keeps `JsonValue` from
+// growing to the 16 byte alignment of `i128`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Repr {
+    Signed(i64),
+    Unsigned(u64),
+    // High and low half of an `i128`
+    Signed128(i64, u64),
+    // High and low half of a `u128` above `i128::MAX`
+    Unsigned128(u64, u64),
+}
+
+// 2^127 and 2^128, the first floats out of range of `i128` and `u128`
+const I128_END: f64 = 170141183460469231731687303715884105728.0;
+const U128_END: f64 = 340282366920938463463374607431768211456.0;
+
+impl Integer {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.repr {
+            Repr::Signed(value) => Some(value),
+            _                   => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.repr {
+            Repr::Signed(value) if value >= 0 => Some(value as u64),
+            Repr::Unsigned(value)             => Some(value),
+            _                                 => None,
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        match self.repr {
+            Repr::Signed(value)        => Some(value as i128),
+            Repr::Unsigned(value)      => Some(value as i128),
+            Repr::Signed128(high, low) => Some((high as i128) << 64 | low as i128),
+            Repr::Unsigned128(..)      => None,
+        }
+    }
+
+    pub fn as_u128(&self) -> Option<u128> {
+        match self.repr {
+            Repr::Unsigned128(high, low) => Some((high as u128) << 64 | low as u128),
+            _ => match self.as_i128() {
+                Some(value) if value >= 0 => Some(value as u128),
+                _                         => None,
+            }
+        }
+    }
+
+    /// Converts to the nearest `f64`, which loses precision for integers
+    /// larger than 2^53.
+    pub fn as_f64(&self) -> f64 {
+        match self.as_i128() {
+            Some(value) => value as f64,
+            None        => self.as_u128().unwrap() as f64,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self.repr {
+            Repr::Signed(value)      => value < 0,
+            Repr::Signed128(high, _) => high < 0,
+            _                        => false,
+        }
+    }
+
+    // Converts a float holding an integer exactly, `None` if it has a
+    // fraction or doesn't fit.
+    pub(crate) fn from_f64(num: f64) -> Option<Integer> {
+        if num.fract() != 0.0 {
+            None
+        } else if (-I128_END .. I128_END).contains(&num) {
+            Some(Integer::from(num as i128))
+        } else if (I128_END .. U128_END).contains(&num) {
+            Some(Integer::from(num as u128))
+        } else {
+            None
+        }
+    }
+
+    // Checks that the float holds exactly the same value, without rounding
+    // either of them.
+    pub(crate) fn eq_f64(&self, num: f64) -> bool {
+        if num.fract() != 0.0 {
+            return false;
+        }
+
+        match self.as_i128() {
+            Some(value) => (-I128_END .. I128_END).contains(&num) && num as i128 == value,
+            None => (I128_END .. U128_END).contains(&num) && self.as_u128() == Some(num as u128),
+        }
+    }
+
+    // Orders the integer against a float exactly, `None` if it's NaN.
+    pub(crate) fn partial_cmp_f64(&self, num: f64) -> Option<Ordering> {
+        if num.is_nan() {
+            return None;
+        }
+
+        // Integers differ by at least 1, so unless the whole part of the
+        // float is equal to the integer, the fraction can't change the order
+        match Integer::from_f64(num.trunc()) {
+            Some(whole) => match self.cmp(&whole) {
+                Ordering::Equal => 0.0.partial_cmp(&num.fract()),
+                order           => Some(order),
+            },
+            None => Some(if num > 0.0 { Ordering::Less } else { Ordering::Greater }),
+        }
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self.repr {
+            Repr::Signed(value)   => itoa::write(writer, value).map(|_| ()),
+            Repr::Unsigned(value) => itoa::write(writer, value).map(|_| ()),
+            _                     => write!(writer, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.repr {
+            Repr::Signed(value)   => value.fmt(f),
+            Repr::Unsigned(value) => value.fmt(f),
+            Repr::Signed128(..)   => self.as_i128().unwrap().fmt(f),
+            Repr::Unsigned128(..) => self.as_u128().unwrap().fmt(f),
+        }
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Integer) -> Ordering {
+        match (self.as_i128(), other.as_i128()) {
+            (Some(left), Some(right)) => left.cmp(&right),
+
+            // Only values above `i128::MAX` don't fit, negative values have
+            // no `u128` and `None` orders below them
+            _ => self.as_u128().cmp(&other.as_u128()),
+        }
+    }
+}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Integer) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<i128> for Integer {
+    fn from(val: i128) -> Integer {
+        if val >= i64::MIN as i128 && val <= u64::MAX as i128 {
+            return if val < 0 {
+                Integer::from(val as i64)
+            } else {
+                Integer::from(val as u64)
+            };
+        }
+
+        Integer {
+            repr: Repr::Signed128((val >> 64) as i64, val as u64)
+        }
+    }
+}
+
+impl From<u128> for Integer {
+    fn from(val: u128) -> Integer {
+        if val <= i128::MAX as u128 {
+            return Integer::from(val as i128);
+        }
+
+        Integer {
+            repr: Repr::Unsigned128((val >> 64) as u64, val as u64)
+        }
+    }
+}
+
+macro_rules! implement_signed {
+    ($($from:ty),*) => {
+        $(
+            impl From<$from> for Integer {
+                fn from(val: $from) -> Integer {
+                    Integer {
+                        repr: Repr::Signed(val as i64)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! implement_unsigned {
+    ($($from:ty),*) => {
+        $(
+            impl From<$from> for Integer {
+                fn from(val: $from) -> Integer {
+                    let val = val as u64;
+
+                    Integer {
+                        repr: if val > i64::MAX as u64 {
+                            Repr::Unsigned(val)
+                        } else {
+                            Repr::Signed(val as i64)
+                        }
+                    }
+                }
+            }
+        )*
+    }
+}
+
+implement_signed!(i8, i16, i32, i64, isize);
+implement_unsigned!(u8, u16, u32, u64, usize);
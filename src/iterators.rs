@@ -1,6 +1,6 @@
-use std::collections::btree_map;
 use std::slice;
 use std::iter::{ Iterator, DoubleEndedIterator };
+use object;
 use JsonValue;
 
 pub enum Members<'a> {
@@ -14,12 +14,12 @@ pub enum MembersMut<'a> {
 }
 
 pub enum Entries<'a> {
-    Some(btree_map::Iter<'a, String, JsonValue>),
+    Some(object::Iter<'a>),
     None
 }
 
 pub enum EntriesMut<'a> {
-    Some(btree_map::IterMut<'a, String, JsonValue>),
+    Some(object::IterMut<'a>),
     None
 }
 
@@ -74,6 +74,7 @@ impl<'a> Iterator for Entries<'a> {
     }
 }
 
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
 impl<'a> DoubleEndedIterator for Entries<'a> {
     fn next_back(&mut self) -> Option<(&'a String, &'a JsonValue)> {
         match *self {
@@ -94,6 +95,7 @@ impl<'a> Iterator for EntriesMut<'a> {
     }
 }
 
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
 impl<'a> DoubleEndedIterator for EntriesMut<'a> {
     fn next_back(&mut self) -> Option<(&'a String, &'a mut JsonValue)> {
         match *self {
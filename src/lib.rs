@@ -59,10 +59,11 @@
 //! ```rust
 //! # #[macro_use] extern crate json;
 //! # fn main() {
+//! # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
 //! let mut data = object!{
-//!     "foo" => false,
-//!     "bar" => json::Null,
 //!     "answer" => 42,
+//!     "bar" => json::Null,
+//!     "foo" => false,
 //!     "list" => array![json::Null, "world", true]
 //! };
 //!
@@ -160,6 +161,7 @@
 //! Putting fields on objects:
 //!
 //! ```
+//! # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
 //! let mut data = json::JsonValue::new_object();
 //!
 //! data["answer"] = 42.into();
@@ -183,6 +185,7 @@
 //! ```
 //! # #[macro_use] extern crate json;
 //! # fn main() {
+//! # if cfg!(any(feature = "preserve_order", feature = "hash_map")) { return; }
 //! let data = object!{
 //!     "name"    => "John Doe",
 //!     "age"     => 30,
@@ -190,77 +193,294 @@
 //! };
 //! assert_eq!(
 //!     data.dump(),
-//!     // Because object is internally using a BTreeMap,
-//!     // the key order is alphabetical
+//!     // By default `json::Object` is a BTreeMap,
+//!     // so the key order is alphabetical
 //!     r#"{"age":30,"canJSON":true,"name":"John Doe"}"#
 //! );
 //! # }
 //! ```
 
-mod codegen;
+pub mod codegen;
 mod parser;
 mod value;
+mod integer;
 mod error;
+mod scan;
+mod writer;
+mod summary;
+mod pointer;
+pub mod query;
+mod patch;
 pub mod iterators;
+pub mod object;
+#[cfg(feature = "preserve_order")]
+mod ordered_map;
 
-pub use error::JsonError;
+pub use error::{ JsonError, ErrorKind, IoError };
 pub use value::JsonValue;
 pub use value::JsonValue::Null;
+pub use object::Object;
+pub use integer::Integer;
+pub use query::JsonPath;
+pub use patch::apply_patch;
 pub type JsonResult<T> = Result<T, JsonError>;
 
-pub use parser::parse;
-use codegen::{ Generator, PrettyGenerator, DumpGenerator, WriterGenerator };
+pub use parser::{ parse, parse_with, ParseOptions };
+pub use writer::JsonWriter;
+pub use summary::SummaryOptions;
+pub use codegen::{ DumpOptions, PrettyOptions, Indent, LineEnding, NonFinite, Theme, Color };
+use codegen::{ Generator, PrettyGenerator, DumpGenerator, WriterGenerator,
+               PrettyWriterGenerator, CompactPrettyGenerator, ColorGenerator, FmtWriter,
+               ByteCounter };
 
-use std::io::Write;
+use std::io::{ self, Write, BufWriter, IsTerminal };
+use std::env;
 use std::collections::HashMap;
 use std::collections::BTreeMap;
 use std::fmt;
 
 pub type Array = Vec<JsonValue>;
-pub type Object = BTreeMap<String, JsonValue>;
 
 impl JsonValue {
     /// Prints out the value as JSON string.
     pub fn dump(&self) -> String {
-        let mut gen = DumpGenerator::new();
-        gen.write_json(self);
-        gen.consume()
+        let mut gen = DumpGenerator::new(DumpOptions::new());
+        gen.write_json(self).expect("Can't fail");
+        gen.consume_json()
+    }
+
+    /// Prints out the value as JSON string, using given `DumpOptions`. Can
+    /// only fail with `NonFinite::Error`.
+    pub fn dump_with(&self, options: DumpOptions) -> JsonResult<String> {
+        let mut gen = DumpGenerator::new(options);
+        try!(gen.write_json(self));
+        Ok(gen.consume_json())
+    }
+
+    /// Number of bytes in the output of `dump`, computed without building
+    /// the output.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = object!{ "name" => "Zażółć", "tags" => array!["a\nb", 1.5] };
+    ///
+    /// assert_eq!(data.serialized_len(), data.dump().len());
+    /// assert_eq!(data.serialized_len_pretty(2), data.pretty(2).len());
+    /// # }
+    /// ```
+    pub fn serialized_len(&self) -> usize {
+        let mut counter = ByteCounter { count: 0 };
+        WriterGenerator::new(&mut counter, DumpOptions::new()).write_json(self).expect("Can't fail");
+        counter.count
+    }
+
+    /// Number of bytes in the output of `dump_with` with the same options.
+    /// Can only fail with `NonFinite::Error`.
+    pub fn serialized_len_with(&self, options: DumpOptions) -> JsonResult<usize> {
+        let mut counter = ByteCounter { count: 0 };
+        try!(WriterGenerator::new(&mut counter, options).write_json(self));
+        Ok(counter.count)
+    }
+
+    /// Number of bytes in the output of `pretty` with the same options,
+    /// computed without building the output.
+    pub fn serialized_len_pretty<O>(&self, options: O) -> usize where O: Into<PrettyOptions> {
+        let options = options.into().infallible();
+        let mut counter = ByteCounter { count: 0 };
+
+        if options.is_width_aware() {
+            CompactPrettyGenerator::new(&mut counter, options).write_document(self).expect("Can't fail");
+        } else {
+            PrettyWriterGenerator::new(&mut counter, options).write_document(self).expect("Can't fail");
+        }
+
+        counter.count
+    }
+
+    /// Prints out the value as JSON string, with the parts over the limits
+    /// of `SummaryOptions` left out, for logging and error messages.
+    pub fn summarize(&self, options: SummaryOptions) -> String {
+        match options.pretty_options() {
+            Some(pretty) => {
+                let mut gen = PrettyGenerator::new(pretty.non_finite(NonFinite::Null));
+                summary::write_summary(&mut gen, self, &options, 0).expect("Can't fail");
+                gen.consume_json()
+            },
+            None => {
+                let mut gen = DumpGenerator::new(DumpOptions::new());
+                summary::write_summary(&mut gen, self, &options, 0).expect("Can't fail");
+                gen.consume_json()
+            }
+        }
     }
 
-    /// Pretty prints out the value as JSON string. Takes an argument that's
-    /// number of spaces to indent new blocks with.
-    pub fn pretty(&self, spaces: u16) -> String {
-        let mut gen = PrettyGenerator::new(spaces);
-        gen.write_json(self);
-        gen.consume()
+    /// Pretty prints out the value as JSON string. Takes either a number of
+    /// spaces to indent new blocks with, or `PrettyOptions`.
+    ///
+    /// NaN and infinite numbers are written as `null` if the options use
+    /// `NonFinite::Error`, use `pretty_with` to get the error instead.
+    pub fn pretty<O>(&self, options: O) -> String where O: Into<PrettyOptions> {
+        self.pretty_with(options.into().infallible()).expect("Can't fail")
+    }
+
+    /// Same as `pretty`. Can only fail with `NonFinite::Error`.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// use json::{ PrettyOptions, NonFinite, JsonError };
+    ///
+    /// let data = array![1, std::f64::NAN];
+    /// let options = PrettyOptions::from(2).non_finite(NonFinite::Error);
+    ///
+    /// assert_eq!(data.pretty_with(options), Err(JsonError::NonFiniteNumber));
+    /// assert_eq!(data.pretty(options), "[\n  1,\n  null\n]");
+    /// # }
+    /// ```
+    pub fn pretty_with<O>(&self, options: O) -> JsonResult<String> where O: Into<PrettyOptions> {
+        let options = options.into();
+
+        if options.is_width_aware() {
+            let mut code = Vec::with_capacity(1024);
+
+            try!(CompactPrettyGenerator::new(&mut code, options).write_document(self));
+
+            // Same as with `PrettyGenerator`, everything written is UTF-8
+            return Ok(unsafe { String::from_utf8_unchecked(code) });
+        }
+
+        let mut gen = PrettyGenerator::new(options);
+        try!(gen.write_document(self));
+        Ok(gen.consume_json())
     }
 
     /// Dumps the JSON as byte stream into an instance of `std::io::Write`.
-    pub fn to_writer<W: Write>(&self, writer: &mut W) {
-        let mut gen = WriterGenerator::new(writer);
-        gen.write_json(self);
+    /// Output is buffered internally, any error reported by the writer is
+    /// returned.
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.to_writer_with(writer, DumpOptions::new())
+    }
+
+    /// Same as `to_writer`, using given `DumpOptions`.
+    pub fn to_writer_with<W: Write>(&self, writer: &mut W, options: DumpOptions) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        try!(WriterGenerator::new(&mut writer, options).write_json(self));
+
+        // Unlike dropping, this reports errors of writing out the buffer
+        writer.into_inner().map(|_| ()).map_err(|err| err.into_error())
+    }
+
+    /// Pretty prints the JSON as byte stream into an instance of
+    /// `std::io::Write`. Takes either a number of spaces to indent new blocks
+    /// with, or `PrettyOptions`.
+    pub fn to_writer_pretty<W, O>(&self, writer: &mut W, options: O) -> io::Result<()>
+    where W: Write, O: Into<PrettyOptions> {
+        let options = options.into();
+        let mut writer = BufWriter::new(writer);
+
+        if options.is_width_aware() {
+            try!(CompactPrettyGenerator::new(&mut writer, options).write_document(self));
+        } else {
+            try!(PrettyWriterGenerator::new(&mut writer, options).write_document(self));
+        }
+
+        writer.into_inner().map(|_| ()).map_err(|err| err.into_error())
+    }
+
+    /// Pretty prints the JSON into an instance of `std::io::Write`, with the
+    /// syntax highlighted using ANSI escape codes and colors of the `Theme`.
+    /// Takes either a number of spaces to indent new blocks with, or
+    /// `PrettyOptions`, `max_width` is ignored.
+    pub fn to_writer_colored<W, O>(&self, writer: &mut W, options: O, theme: Theme) -> io::Result<()>
+    where W: Write, O: Into<PrettyOptions> {
+        let mut writer = BufWriter::new(writer);
+
+        try!(ColorGenerator::new(&mut writer, options.into(), theme).write_document(self));
+
+        writer.into_inner().map(|_| ()).map_err(|err| err.into_error())
+    }
+
+    /// Same as `to_writer_colored` when `colored` is `true`, same as
+    /// `to_writer_pretty` when it isn't, or when the `NO_COLOR` environment
+    /// variable is set.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = object!{ "colors" => false };
+    /// let mut buffer = Vec::new();
+    ///
+    /// data.to_writer_colored_if(&mut buffer, false, 2, json::Theme::new()).unwrap();
+    ///
+    /// assert_eq!(buffer, data.pretty(2).into_bytes());
+    /// # }
+    /// ```
+    pub fn to_writer_colored_if<W, O>(&self, writer: &mut W, colored: bool, options: O, theme: Theme) -> io::Result<()>
+    where W: Write, O: Into<PrettyOptions> {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+        if no_color || !colored {
+            return self.to_writer_pretty(writer, options);
+        }
+
+        self.to_writer_colored(writer, options, theme)
+    }
+
+    /// Same as `to_writer_colored` when `writer` is a terminal, same as
+    /// `to_writer_pretty` when it isn't, or when the `NO_COLOR` environment
+    /// variable is set.
+    ///
+    /// ```no_run
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = object!{ "colors" => true };
+    ///
+    /// data.to_terminal(&mut std::io::stdout(), 2, json::Theme::new()).unwrap();
+    /// # }
+    /// ```
+    pub fn to_terminal<W, O>(&self, writer: &mut W, options: O, theme: Theme) -> io::Result<()>
+    where W: Write + IsTerminal, O: Into<PrettyOptions> {
+        let colored = writer.is_terminal();
+
+        self.to_writer_colored_if(writer, colored, options, theme)
     }
 }
 
-/// Implements formatting
+/// Implements formatting. The JSON is written straight into the formatter,
+/// without building a `String` first.
 ///
 /// ```
 /// # use json;
+/// use std::fmt::Write;
+///
 /// let data = json::parse(r#"{"url":"https://github.com/"}"#).unwrap();
 /// println!("{}", data);
 /// println!("{:#}", data);
+///
+/// let mut buffer = String::from("data: ");
+/// write!(buffer, "{}", data).unwrap();
+/// assert_eq!(buffer, r#"data: {"url":"https://github.com/"}"#);
 /// ```
 impl fmt::Display for JsonValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if f.alternate() {
-            f.write_str(&self.pretty(4))
+            PrettyWriterGenerator::new(&mut FmtWriter::new(f), PrettyOptions::new())
+                .write_json(self)
+                .map_err(|_| fmt::Error)
         } else {
             match *self {
                 JsonValue::String(ref value)  => value.fmt(f),
                 JsonValue::Number(ref value)  => value.fmt(f),
+                JsonValue::Integer(ref value) => value.fmt(f),
                 JsonValue::Boolean(ref value) => value.fmt(f),
                 JsonValue::Null               => f.write_str("null"),
-                _                             => f.write_str(&self.dump())
+                _                             => {
+                    WriterGenerator::new(&mut FmtWriter::new(f), DumpOptions::new())
+                        .write_json(self)
+                        .map_err(|_| fmt::Error)
+                }
             }
         }
     }
@@ -277,11 +497,12 @@ pub fn stringify<T>(root: T) -> String where T: Into<JsonValue> {
     root.dump()
 }
 
-/// Pretty prints out the value as JSON string. Second argument is a
-/// number of spaces to indent new blocks with.
-pub fn stringify_pretty<T>(root: T, spaces: u16) -> String where T: Into<JsonValue> {
+/// Pretty prints out the value as JSON string. Second argument is either a
+/// number of spaces to indent new blocks with, or `PrettyOptions`.
+pub fn stringify_pretty<T, O>(root: T, options: O) -> String
+where T: Into<JsonValue>, O: Into<PrettyOptions> {
     let root: JsonValue = root.into();
-    root.pretty(spaces)
+    root.pretty(options)
 }
 
 
@@ -305,9 +526,7 @@ macro_rules! object {
     {} => ($crate::JsonValue::new_object());
 
     { $( $key:expr => $value:expr ),* } => ({
-        use std::collections::BTreeMap;
-
-        let mut object = BTreeMap::new();
+        let mut object = $crate::Object::new();
 
         $(
             object.insert($key.into(), $value.into());
@@ -350,43 +569,39 @@ macro_rules! implement_extras {
     }
 }
 
-macro_rules! implement {
-    ($to:ident, $from:ty as $wanted:ty) => {
+// Numbers compare through `JsonValue`, which compares integers and floats
+// exactly.
+macro_rules! implement_number {
+    ($to:ident, $from:ty) => {
         impl From<$from> for JsonValue {
             fn from(val: $from) -> JsonValue {
-                JsonValue::$to(val as $wanted)
+                JsonValue::$to(val.into())
             }
         }
 
         impl PartialEq<$from> for JsonValue {
             fn eq(&self, other: &$from) -> bool {
-                match *self {
-                    JsonValue::$to(ref value) => value == &(*other as $wanted),
-                    _ => false
-                }
+                self.eq(&JsonValue::from(*other))
             }
         }
 
         impl<'a> PartialEq<$from> for &'a JsonValue {
             fn eq(&self, other: &$from) -> bool {
-                match **self {
-                    JsonValue::$to(ref value) => value == &(*other as $wanted),
-                    _ => false
-                }
+                (**self).eq(&JsonValue::from(*other))
             }
         }
 
         impl PartialEq<JsonValue> for $from {
             fn eq(&self, other: &JsonValue) -> bool {
-                match *other {
-                    JsonValue::$to(ref value) => value == &(*self as $wanted),
-                    _ => false
-                }
+                JsonValue::from(*self).eq(other)
             }
         }
 
         implement_extras!($from);
-    };
+    }
+}
+
+macro_rules! implement {
     ($to:ident, $from:ty) => {
         impl From<$from> for JsonValue {
             fn from(val: $from) -> JsonValue {
@@ -441,14 +656,14 @@ impl<'a> From<Option<&'a str>> for JsonValue {
 }
 
 impl From<HashMap<String, JsonValue>> for JsonValue {
-    fn from(mut val: HashMap<String, JsonValue>) -> JsonValue {
-        let mut object = BTreeMap::new();
-
-        for (key, value) in val.drain() {
-            object.insert(key, value);
-        }
+    fn from(val: HashMap<String, JsonValue>) -> JsonValue {
+        JsonValue::Object(val.into())
+    }
+}
 
-        JsonValue::Object(object)
+impl From<BTreeMap<String, JsonValue>> for JsonValue {
+    fn from(val: BTreeMap<String, JsonValue>) -> JsonValue {
+        JsonValue::Object(val.into())
     }
 }
 
@@ -507,18 +722,20 @@ impl<'a> PartialEq<JsonValue> for str {
 }
 
 implement!(String, String);
-implement!(Number, isize as f64);
-implement!(Number, usize as f64);
-implement!(Number, i8 as f64);
-implement!(Number, i16 as f64);
-implement!(Number, i32 as f64);
-implement!(Number, i64 as f64);
-implement!(Number, u8 as f64);
-implement!(Number, u16 as f64);
-implement!(Number, u32 as f64);
-implement!(Number, u64 as f64);
-implement!(Number, f32 as f64);
-implement!(Number, f64);
+implement_number!(Integer, isize);
+implement_number!(Integer, usize);
+implement_number!(Integer, i8);
+implement_number!(Integer, i16);
+implement_number!(Integer, i32);
+implement_number!(Integer, i64);
+implement_number!(Integer, i128);
+implement_number!(Integer, u8);
+implement_number!(Integer, u16);
+implement_number!(Integer, u32);
+implement_number!(Integer, u64);
+implement_number!(Integer, u128);
+implement_number!(Number, f32);
+implement_number!(Number, f64);
 implement!(Object, Object);
 implement!(Array, Array);
 implement!(Boolean, bool);
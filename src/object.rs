@@ -0,0 +1,413 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::iter::{ Iterator, ExactSizeIterator, FromIterator };
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+use std::iter::DoubleEndedIterator;
+use std::fmt;
+use JsonValue;
+
+#[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
+mod backend {
+    use std::collections::btree_map;
+    use JsonValue;
+
+    pub use std::collections::btree_map::Entry;
+
+    pub type Map = btree_map::BTreeMap<String, JsonValue>;
+    pub type VacantEntry<'a> = btree_map::VacantEntry<'a, String, JsonValue>;
+    pub type OccupiedEntry<'a> = btree_map::OccupiedEntry<'a, String, JsonValue>;
+    pub type Iter<'a> = btree_map::Iter<'a, String, JsonValue>;
+    pub type IterMut<'a> = btree_map::IterMut<'a, String, JsonValue>;
+    pub type IntoIter = btree_map::IntoIter<String, JsonValue>;
+
+    // Entries are kept in an order of their own only with `preserve_order`
+    pub fn position(_map: &Map, _key: &str) -> Option<usize> {
+        None
+    }
+
+    pub fn insert_at(map: &mut Map, _index: usize, key: String, value: JsonValue) {
+        map.insert(key, value);
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+mod backend {
+    pub use ordered_map::{ Entry, VacantEntry, OccupiedEntry, Iter, IterMut, IntoIter };
+    pub use ordered_map::OrderedMap as Map;
+    use JsonValue;
+
+    pub fn position(map: &Map, key: &str) -> Option<usize> {
+        map.position(key)
+    }
+
+    pub fn insert_at(map: &mut Map, index: usize, key: String, value: JsonValue) {
+        map.insert_at(index, key, value)
+    }
+}
+
+#[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+mod backend {
+    use std::collections::hash_map;
+    use JsonValue;
+
+    pub use std::collections::hash_map::Entry;
+
+    pub type Map = hash_map::HashMap<String, JsonValue>;
+    pub type VacantEntry<'a> = hash_map::VacantEntry<'a, String, JsonValue>;
+    pub type OccupiedEntry<'a> = hash_map::OccupiedEntry<'a, String, JsonValue>;
+    pub type Iter<'a> = hash_map::Iter<'a, String, JsonValue>;
+    pub type IterMut<'a> = hash_map::IterMut<'a, String, JsonValue>;
+    pub type IntoIter = hash_map::IntoIter<String, JsonValue>;
+
+    // Entries are kept in an order of their own only with `preserve_order`
+    pub fn position(_map: &Map, _key: &str) -> Option<usize> {
+        None
+    }
+
+    pub fn insert_at(map: &mut Map, _index: usize, key: String, value: JsonValue) {
+        map.insert(key, value);
+    }
+}
+
+/// Entries of `JsonValue::Object`. The map behind it is picked with cargo
+/// features:
+///
+/// - by default entries are kept sorted by key in a `BTreeMap`,
+/// - `preserve_order` keeps entries in the order they were inserted in. They
+///   are stored in a vector, searched linearly while there are few of them
+///   and through a hash index once there are more than 16,
+/// - `hash_map` stores entries in a `HashMap`, their order is unspecified.
+///   Iterating wraps the `HashMap` iterators without copying the entries,
+///   so `Iter`, `IterMut`, `IntoIter` and the `entries` iterators of
+///   `JsonValue` don't implement `DoubleEndedIterator` with this map.
+///
+/// When both `preserve_order` and `hash_map` are enabled, `preserve_order`
+/// wins, so that enabling one in a dependency never changes the order seen
+/// by a crate relying on the other.
+///
+/// ```
+/// use json::object::{ Object, Entry };
+///
+/// let mut object = Object::new();
+///
+/// object.insert("answer".into(), 42.into());
+///
+/// match object.entry("list".into()) {
+///     Entry::Vacant(entry) => { entry.insert(json::JsonValue::new_array()); },
+///     Entry::Occupied(_) => unreachable!(),
+/// }
+///
+/// *object.entry("answer".into()).or_insert(0.into()) = 7.into();
+///
+/// assert_eq!(object.len(), 2);
+/// assert!(object.get("answer").unwrap() == 7);
+/// assert!(object.get("list").unwrap().is_array());
+/// ```
+#[derive(Clone, Default, PartialEq)]
+pub struct Object {
+    map: backend::Map,
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Object {
+            map: backend::Map::new(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.map.get(key)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        self.map.get_mut(key)
+    }
+
+    /// Inserts an entry, returning the value previously stored under the
+    /// key. Replacing a value doesn't change the position of the entry.
+    #[inline]
+    pub fn insert(&mut self, key: String, value: JsonValue) -> Option<JsonValue> {
+        self.map.insert(key, value)
+    }
+
+    /// Removes an entry, returning its value. The order of the remaining
+    /// entries doesn't change.
+    #[inline]
+    pub fn remove(&mut self, key: &str) -> Option<JsonValue> {
+        self.map.remove(key)
+    }
+
+    // Position of the entry among the others, if the map keeps entries in
+    // an order of their own, which only `preserve_order` does.
+    pub(crate) fn position(&self, key: &str) -> Option<usize> {
+        backend::position(&self.map, key)
+    }
+
+    // Inserts an entry that isn't present at the position `position` gave
+    // for it, so that a removed entry can be put back where it was.
+    pub(crate) fn insert_at(&mut self, position: usize, key: String, value: JsonValue) {
+        backend::insert_at(&mut self.map, position, key, value)
+    }
+
+    /// Gets the entry for the key, to read, insert or remove it in place.
+    pub fn entry(&mut self, key: String) -> Entry {
+        match self.map.entry(key) {
+            backend::Entry::Vacant(inner)   => Entry::Vacant(VacantEntry { inner: inner }),
+            backend::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry { inner: inner }),
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.map.iter()
+        }
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            inner: self.map.iter_mut()
+        }
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+impl FromIterator<(String, JsonValue)> for Object {
+    fn from_iter<I>(iter: I) -> Self where I: IntoIterator<Item = (String, JsonValue)> {
+        Object {
+            map: iter.into_iter().collect()
+        }
+    }
+}
+
+impl From<BTreeMap<String, JsonValue>> for Object {
+    fn from(map: BTreeMap<String, JsonValue>) -> Object {
+        map.into_iter().collect()
+    }
+}
+
+impl From<HashMap<String, JsonValue>> for Object {
+    fn from(map: HashMap<String, JsonValue>) -> Object {
+        map.into_iter().collect()
+    }
+}
+
+impl IntoIterator for Object {
+    type Item = (String, JsonValue);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            inner: self.map.into_iter()
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Object {
+    type Item = (&'a String, &'a JsonValue);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Object {
+    type Item = (&'a String, &'a mut JsonValue);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+/// Entry of an `Object`, returned by `Object::entry`.
+pub enum Entry<'a> {
+    Vacant(VacantEntry<'a>),
+    Occupied(OccupiedEntry<'a>),
+}
+
+pub struct VacantEntry<'a> {
+    inner: backend::VacantEntry<'a>
+}
+
+pub struct OccupiedEntry<'a> {
+    inner: backend::OccupiedEntry<'a>
+}
+
+impl<'a> Entry<'a> {
+    pub fn key(&self) -> &String {
+        match *self {
+            Entry::Vacant(ref entry)   => entry.key(),
+            Entry::Occupied(ref entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value of the entry, inserting `default` first if the
+    /// entry is vacant.
+    pub fn or_insert(self, default: JsonValue) -> &'a mut JsonValue {
+        match self {
+            Entry::Vacant(entry)   => entry.insert(default),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// Same as `or_insert`, only calls `default` when the entry is vacant.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut JsonValue where F: FnOnce() -> JsonValue {
+        match self {
+            Entry::Vacant(entry)   => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    pub fn key(&self) -> &String {
+        self.inner.key()
+    }
+
+    pub fn insert(self, value: JsonValue) -> &'a mut JsonValue {
+        self.inner.insert(value)
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub fn key(&self) -> &String {
+        self.inner.key()
+    }
+
+    pub fn get(&self) -> &JsonValue {
+        self.inner.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut JsonValue {
+        self.inner.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut JsonValue {
+        self.inner.into_mut()
+    }
+
+    /// Replaces the value of the entry, returning the old one.
+    pub fn insert(&mut self, value: JsonValue) -> JsonValue {
+        self.inner.insert(value)
+    }
+
+    pub fn remove(self) -> JsonValue {
+        self.inner.remove()
+    }
+}
+
+/// Iterator over the entries of an `Object`. Can't be reversed with the
+/// `hash_map` feature.
+pub struct Iter<'a> {
+    inner: backend::Iter<'a>
+}
+
+/// Mutable iterator over the entries of an `Object`. Can't be reversed
+/// with the `hash_map` feature.
+pub struct IterMut<'a> {
+    inner: backend::IterMut<'a>
+}
+
+/// Iterator over the entries of an `Object`, taking them out of it. Can't
+/// be reversed with the `hash_map` feature.
+pub struct IntoIter {
+    inner: backend::IntoIter
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a JsonValue);
+
+    #[inline]
+    fn next(&mut self) -> Option<(&'a String, &'a JsonValue)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(&'a String, &'a JsonValue)> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a String, &'a mut JsonValue);
+
+    #[inline]
+    fn next(&mut self) -> Option<(&'a String, &'a mut JsonValue)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+impl<'a> DoubleEndedIterator for IterMut<'a> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(&'a String, &'a mut JsonValue)> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for IterMut<'a> {}
+
+impl Iterator for IntoIter {
+    type Item = (String, JsonValue);
+
+    #[inline]
+    fn next(&mut self) -> Option<(String, JsonValue)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+impl DoubleEndedIterator for IntoIter {
+    #[inline]
+    fn next_back(&mut self) -> Option<(String, JsonValue)> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
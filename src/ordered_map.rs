@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::iter::{ Iterator, DoubleEndedIterator, ExactSizeIterator, FromIterator };
+use std::{ fmt, mem, slice, vec };
+use JsonValue;
+
+// Objects with more keys than this get a hash index, smaller ones are
+// searched linearly, which is faster for them.
+const INDEX_THRESHOLD: usize = 16;
+
+// Map that keeps its entries in the order they were inserted in, backing
+// `Object` with the `preserve_order` feature. Two maps are equal when they
+// have the same entries, regardless of their order.
+#[derive(Clone, Default)]
+pub struct OrderedMap {
+    entries: Vec<(String, JsonValue)>,
+
+    // Empty until there are more than `INDEX_THRESHOLD` entries
+    index: HashMap<String, usize>,
+}
+
+impl OrderedMap {
+    pub fn new() -> Self {
+        OrderedMap {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    pub fn position(&self, key: &str) -> Option<usize> {
+        if self.entries.len() > INDEX_THRESHOLD {
+            return self.index.get(key).cloned();
+        }
+
+        self.entries.iter().position(|entry| entry.0 == key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.position(key).map(|index| &self.entries[index].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        match self.position(key) {
+            Some(index) => Some(&mut self.entries[index].1),
+            None        => None,
+        }
+    }
+
+    // Inserts the entry at the end, unless the key is already present, in
+    // which case the value is replaced in place and the old one returned.
+    pub fn insert(&mut self, key: String, value: JsonValue) -> Option<JsonValue> {
+        if let Some(index) = self.position(&key) {
+            return Some(mem::replace(&mut self.entries[index].1, value));
+        }
+
+        self.push(key, value);
+
+        None
+    }
+
+    // Removes the entry, keeping the order of the remaining ones.
+    pub fn remove(&mut self, key: &str) -> Option<JsonValue> {
+        let index = self.position(key);
+
+        index.map(|index| self.remove_at(index))
+    }
+
+    // Inserts the entry at the position, shifting the ones after it, unless
+    // the key is already present, in which case the value is replaced.
+    pub fn insert_at(&mut self, index: usize, key: String, value: JsonValue) {
+        if let Some(position) = self.position(&key) {
+            self.entries[position].1 = value;
+            return;
+        }
+
+        let last = self.push(key, value);
+
+        if index >= last {
+            return;
+        }
+
+        self.entries[index ..].rotate_right(1);
+
+        if self.entries.len() > INDEX_THRESHOLD {
+            for position in self.index.values_mut() {
+                if *position >= index && *position < last {
+                    *position += 1;
+                }
+            }
+
+            self.index.insert(self.entries[index].0.clone(), index);
+        }
+    }
+
+    pub fn entry(&mut self, key: String) -> Entry {
+        match self.position(&key) {
+            Some(index) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                index: index,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                map: self,
+                key: key,
+            }),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.entries.iter()
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            inner: self.entries.iter_mut()
+        }
+    }
+
+    fn push(&mut self, key: String, value: JsonValue) -> usize {
+        let index = self.entries.len();
+
+        if index == INDEX_THRESHOLD {
+            for (position, entry) in self.entries.iter().enumerate() {
+                self.index.insert(entry.0.clone(), position);
+            }
+        }
+
+        if index >= INDEX_THRESHOLD {
+            self.index.insert(key.clone(), index);
+        }
+
+        self.entries.push((key, value));
+
+        index
+    }
+
+    fn remove_at(&mut self, index: usize) -> JsonValue {
+        let (key, value) = self.entries.remove(index);
+
+        if self.entries.len() > INDEX_THRESHOLD {
+            self.index.remove(&key);
+
+            for position in self.index.values_mut() {
+                if *position > index {
+                    *position -= 1;
+                }
+            }
+        } else {
+            self.index.clear();
+        }
+
+        value
+    }
+}
+
+impl PartialEq for OrderedMap {
+    fn eq(&self, other: &OrderedMap) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl fmt::Debug for OrderedMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl FromIterator<(String, JsonValue)> for OrderedMap {
+    fn from_iter<I>(iter: I) -> Self where I: IntoIterator<Item = (String, JsonValue)> {
+        let mut map = OrderedMap::new();
+
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+impl IntoIterator for OrderedMap {
+    type Item = (String, JsonValue);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            inner: self.entries.into_iter()
+        }
+    }
+}
+
+pub enum Entry<'a> {
+    Vacant(VacantEntry<'a>),
+    Occupied(OccupiedEntry<'a>),
+}
+
+pub struct VacantEntry<'a> {
+    map: &'a mut OrderedMap,
+    key: String,
+}
+
+pub struct OccupiedEntry<'a> {
+    map: &'a mut OrderedMap,
+    index: usize,
+}
+
+impl<'a> VacantEntry<'a> {
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+
+    pub fn insert(self, value: JsonValue) -> &'a mut JsonValue {
+        let index = self.map.push(self.key, value);
+
+        &mut self.map.entries[index].1
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub fn key(&self) -> &String {
+        &self.map.entries[self.index].0
+    }
+
+    pub fn get(&self) -> &JsonValue {
+        &self.map.entries[self.index].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut JsonValue {
+        &mut self.map.entries[self.index].1
+    }
+
+    pub fn into_mut(self) -> &'a mut JsonValue {
+        &mut self.map.entries[self.index].1
+    }
+
+    pub fn insert(&mut self, value: JsonValue) -> JsonValue {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> JsonValue {
+        self.map.remove_at(self.index)
+    }
+}
+
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, (String, JsonValue)>
+}
+
+pub struct IterMut<'a> {
+    inner: slice::IterMut<'a, (String, JsonValue)>
+}
+
+pub struct IntoIter {
+    inner: vec::IntoIter<(String, JsonValue)>
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a JsonValue);
+
+    fn next(&mut self) -> Option<(&'a String, &'a JsonValue)> {
+        self.inner.next().map(|entry| (&entry.0, &entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<(&'a String, &'a JsonValue)> {
+        self.inner.next_back().map(|entry| (&entry.0, &entry.1))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a String, &'a mut JsonValue);
+
+    fn next(&mut self) -> Option<(&'a String, &'a mut JsonValue)> {
+        self.inner.next().map(|&mut (ref key, ref mut value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for IterMut<'a> {
+    fn next_back(&mut self) -> Option<(&'a String, &'a mut JsonValue)> {
+        self.inner.next_back().map(|&mut (ref key, ref mut value)| (key, value))
+    }
+}
+
+impl<'a> ExactSizeIterator for IterMut<'a> {}
+
+impl Iterator for IntoIter {
+    type Item = (String, JsonValue);
+
+    fn next(&mut self) -> Option<(String, JsonValue)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for IntoIter {
+    fn next_back(&mut self) -> Option<(String, JsonValue)> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
@@ -1,25 +1,107 @@
-use std::{ str, char, f64 };
-use std::collections::BTreeMap;
-use { JsonValue, JsonError, JsonResult };
+use std::{ str, char, f64, i64, u64, i128, u128 };
+use error::push_path_key;
+use scan;
+use { JsonValue, JsonError, JsonResult, Object, Integer };
 
 const MAX_PRECISION: u64 = 576460752303423500;
 
+// Sets of tokens the parser was expecting at the point of an error,
+// reported in the `expected` field of `JsonError`.
+type Expected = &'static [&'static str];
+
+const NOTHING: Expected            = &[];
+const VALUE: Expected              = &["value"];
+const VALUE_OR_ARRAY_END: Expected = &["value", "']'"];
+const KEY: Expected                = &["string"];
+const KEY_OR_OBJECT_END: Expected  = &["string", "'}'"];
+const COLON: Expected              = &["':'"];
+const ARRAY_NEXT: Expected         = &["','", "']'"];
+const OBJECT_NEXT: Expected        = &["','", "'}'"];
+const CLOSING_QUOTE: Expected      = &["'\"'"];
+const ESCAPE: Expected             = &["escape sequence"];
+const HEX_DIGIT: Expected          = &["hex digit"];
+const LOW_SURROGATE: Expected      = &["'\\u'"];
+const DIGIT: Expected              = &["digit"];
+const END_OF_JSON: Expected        = &["end of JSON"];
+
+/// Options for `json::parse_with`.
+///
+/// ```
+/// use json::{ ParseOptions, JsonError };
+///
+/// let source = "[[[[1]]]]";
+///
+/// assert!(json::parse(source).is_ok());
+/// assert!(json::parse_with(source, ParseOptions::new().max_depth(4)).is_ok());
+/// assert_eq!(json::parse_with(source, ParseOptions::new().max_depth(3)), Err(JsonError::ExceededDepthLimit));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    max_depth: Option<usize>,
+}
+
+impl ParseOptions {
+    /// Same as `json::parse`, arrays and objects can be nested any depth.
+    pub fn new() -> Self {
+        ParseOptions {
+            max_depth: None,
+        }
+    }
+
+    /// Fail with `JsonError::ExceededDepthLimit` on arrays and objects
+    /// nested deeper than this. The parser is recursive, so without a limit
+    /// a malicious document can make it run out of stack.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::new()
+    }
+}
+
 struct Position {
     pub line: usize,
     pub column: usize,
 }
 
+// A container on the way from the root to the value being parsed, used to
+// describe where in the document an error occurred.
+enum Frame {
+    // Index of the current item.
+    Array(usize),
+
+    // Byte offset of the first character of the current key in the source,
+    // `None` until the key has been read.
+    Object(Option<usize>),
+}
+
+// Errors that occur between the values of a container, rather than inside
+// one of them.
+const BETWEEN_VALUES: [Expected; 5] = [KEY_OR_OBJECT_END, KEY, COLON, ARRAY_NEXT, OBJECT_NEXT];
+
 struct Parser<'a> {
     source: &'a str,
     byte_ptr: *const u8,
     index: usize,
     length: usize,
+    depth: usize,
+    max_depth: Option<usize>,
+
+    // Newlines can only occur in whitespace outside of strings, so they are
+    // counted while skipping whitespace. This way finding the position of
+    // an error doesn't require scanning the source from the start.
+    line: usize,
+    line_start: usize,
 }
 
 macro_rules! expect_byte {
-    ($parser:ident) => ({
+    ($parser:ident, $expected:expr) => ({
         if $parser.is_eof() {
-            return Err(JsonError::UnexpectedEndOfJson);
+            return $parser.unexpected_end_of_json($expected);
         }
 
         let ch = $parser.read_byte();
@@ -29,11 +111,11 @@ macro_rules! expect_byte {
 }
 
 macro_rules! sequence {
-    ($parser:ident, $( $ch:pat ),*) => {
+    ($parser:ident, $expected:expr, $( $ch:pat ),*) => {
         $(
-            match expect_byte!($parser) {
+            match expect_byte!($parser, $expected) {
                 $ch => {},
-                ch  => return $parser.unexpected_character(ch),
+                ch  => return $parser.unexpected_character(ch, $expected),
             }
         )*
     }
@@ -57,14 +139,20 @@ macro_rules! read_num {
 }
 
 macro_rules! consume_whitespace {
-    ($parser:ident, $ch:ident) => {
+    ($parser:ident, $ch:ident, $expected:expr) => {
         match $ch {
             // whitespace
             9 ... 13 | 32 => {
                 loop {
-                    match expect_byte!($parser) {
+                    if $ch == b'\n' {
+                        $parser.new_line();
+                    }
+
+                    $ch = expect_byte!($parser, $expected);
+
+                    match $ch {
                         9 ... 13 | 32 => {},
-                        ch            => { $ch = ch; break }
+                        _             => break
                     }
                 }
             },
@@ -74,26 +162,26 @@ macro_rules! consume_whitespace {
 }
 
 macro_rules! expect {
-    ($parser:ident, $byte:expr) => ({
-        let mut ch = expect_byte!($parser);
+    ($parser:ident, $byte:expr, $expected:expr) => ({
+        let mut ch = expect_byte!($parser, $expected);
 
-        consume_whitespace!($parser, ch);
+        consume_whitespace!($parser, ch, $expected);
 
         if ch != $byte {
-            return $parser.unexpected_character(ch)
+            return $parser.unexpected_character(ch, $expected)
         }
     });
 
-    {$parser:ident $(, $byte:pat => $then:expr )*} => ({
-        let mut ch = expect_byte!($parser);
+    {$parser:ident, $expected:expr $(, $byte:pat => $then:expr )*} => ({
+        let mut ch = expect_byte!($parser, $expected);
 
-        consume_whitespace!($parser, ch);
+        consume_whitespace!($parser, ch, $expected);
 
         match ch {
             $(
                 $byte => $then,
             )*
-            _ => return $parser.unexpected_character(ch)
+            _ => return $parser.unexpected_character(ch, $expected)
         }
 
     })
@@ -106,7 +194,7 @@ const __: bool = true;
 
 // Look up table that marks which characters are allowed in their raw
 // form in a string.
-static ALLOWED: [bool; 256] = [
+pub(crate) static ALLOWED: [bool; 256] = [
 // 0   1   2   3   4   5   6   7   8   9   A   B   C   D   E   F
   CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, // 0
   CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, CT, // 1
@@ -128,27 +216,15 @@ static ALLOWED: [bool; 256] = [
 
 macro_rules! expect_string {
     ($parser:ident) => ({
-        let result: String;// = unsafe { mem::uninitialized() };
         let start = $parser.index;
 
-        loop {
-            let ch = expect_byte!($parser);
-            if ALLOWED[ch as usize] {
-                continue;
-            }
-            if ch == b'"' {
-                result = (&$parser.source[start .. $parser.index - 1]).into();
-                break;
-            }
-            if ch == b'\\' {
-                result = try!($parser.read_complex_string(start));
-                break;
-            }
+        $parser.skip_plain_string();
 
-            return $parser.unexpected_character(ch);
+        match expect_byte!($parser, CLOSING_QUOTE) {
+            b'"'  => String::from(&$parser.source[start .. $parser.index - 1]),
+            b'\\' => try!($parser.read_complex_string(start)),
+            ch    => return $parser.unexpected_character(ch, NOTHING)
         }
-
-        result
     })
 }
 
@@ -185,22 +261,61 @@ fn make_float(num: u64, e: i32) -> f64 {
     (num as f64) * exponent_to_power(e)
 }
 
+fn make_number(num: f64, negative: bool) -> JsonValue {
+    JsonValue::Number(if negative { -num } else { num })
+}
+
+fn make_integer(num: u64, negative: bool) -> JsonValue {
+    if !negative {
+        JsonValue::Integer(Integer::from(num))
+    } else if num <= i64::MAX as u64 + 1 {
+        JsonValue::Integer(Integer::from((num as i64).wrapping_neg()))
+    } else {
+        make_big_integer(num as u128, negative)
+    }
+}
+
+// Integers below `i128::MIN` don't fit `Integer` and become floats
+fn make_big_integer(num: u128, negative: bool) -> JsonValue {
+    if !negative {
+        JsonValue::Integer(Integer::from(num))
+    } else if num <= i128::MAX as u128 + 1 {
+        JsonValue::Integer(Integer::from((num as i128).wrapping_neg()))
+    } else {
+        JsonValue::Number(-(num as f64))
+    }
+}
+
+// Whether appending the digit would overflow u64
+#[inline(always)]
+fn overflows(num: u64, digit: u64) -> bool {
+    num >= u64::MAX / 10 && (num > u64::MAX / 10 || digit > u64::MAX % 10)
+}
+
+// Drops the lowest digits of an integer until it fits u64, returning it
+// along with the number of digits dropped, to continue reading it as float.
+fn shorten(mut num: u128) -> (u64, i32) {
+    let mut e = 0;
+
+    while num > u64::MAX as u128 {
+        num /= 10;
+        e += 1;
+    }
+
+    (num as u64, e)
+}
+
 macro_rules! expect_number {
-    ($parser:ident, $first:ident) => ({
+    ($parser:ident, $first:ident, $negative:expr) => ({
         let mut num = ($first - b'0') as u64;
 
-        let result: f64;
+        let result: JsonValue;
 
-        // Cap on how many iterations we do while reading to u64
-        // in order to avoid an overflow.
+        // Digits are read into u64 for as long as they fit, longer integers
+        // continue in u128.
         loop {
-            if num >= 576460752303423500 {
-                result = try!($parser.read_big_number(num));
-                break;
-            }
-
             if $parser.is_eof() {
-                result = num as f64;
+                result = make_integer(num, $negative);
                 break;
             }
 
@@ -208,16 +323,23 @@ macro_rules! expect_number {
 
             match ch {
                 b'0' ... b'9' => {
+                    let digit = (ch - b'0') as u64;
+
+                    if overflows(num, digit) {
+                        result = try!($parser.read_big_integer(num, $negative));
+                        break;
+                    }
+
                     $parser.bump();
                     // Avoid multiplication with bitshifts and addition
-                    num = (num << 1) + (num << 3) + (ch - b'0') as u64;
+                    num = (num << 1) + (num << 3) + digit;
                 },
                 b'.' | b'e' | b'E' => {
-                    result = try!($parser.read_number_with_fraction(num, 0));
+                    result = make_number(try!($parser.read_number_with_fraction(num, 0)), $negative);
                     break;
                 },
                 _  => {
-                    result = num as f64;
+                    result = make_integer(num, $negative);
                     break;
                 }
             }
@@ -228,10 +350,10 @@ macro_rules! expect_number {
 }
 
 macro_rules! expect_value {
-    {$parser:ident $(, $byte:pat => $then:expr )*} => ({
-        let mut ch = expect_byte!($parser);
+    {$parser:ident, $expected:expr $(, $byte:pat => $then:expr )*} => ({
+        let mut ch = expect_byte!($parser, $expected);
 
-        consume_whitespace!($parser, ch);
+        consume_whitespace!($parser, ch, $expected);
 
         match ch {
             $(
@@ -240,38 +362,33 @@ macro_rules! expect_value {
             b'[' => JsonValue::Array(try!($parser.read_array())),
             b'{' => JsonValue::Object(try!($parser.read_object())),
             b'"' => JsonValue::String(expect_string!($parser)),
-            b'0' => {
-                let num = try!($parser.read_number_with_fraction(0, 0));
-                JsonValue::Number(num)
-            },
-            b'1' ... b'9' => {
-                let num = expect_number!($parser, ch);
-                JsonValue::Number(num)
-            },
+            b'0' => try!($parser.read_zero(false)),
+            b'1' ... b'9' => expect_number!($parser, ch, false),
             b'-' => {
-                let ch = expect_byte!($parser);
-                let num = match ch {
-                    b'0' => try!($parser.read_number_with_fraction(0, 0)),
-                    b'1' ... b'9' => expect_number!($parser, ch),
-                    _    => return $parser.unexpected_character(ch)
-                };
-                JsonValue::Number(-num)
+                let ch = expect_byte!($parser, DIGIT);
+                match ch {
+                    b'0' => try!($parser.read_zero(true)),
+                    b'1' ... b'9' => expect_number!($parser, ch, true),
+                    _    => return $parser.unexpected_character(ch, DIGIT)
+                }
             }
             b't' => {
-                sequence!($parser, b'r', b'u', b'e');
+                sequence!($parser, &["true"], b'r', b'u', b'e');
                 JsonValue::Boolean(true)
             },
             b'f' => {
-                sequence!($parser, b'a', b'l', b's', b'e');
+                sequence!($parser, &["false"], b'a', b'l', b's', b'e');
                 JsonValue::Boolean(false)
             },
             b'n' => {
-                sequence!($parser, b'u', b'l', b'l');
+                sequence!($parser, &["null"], b'u', b'l', b'l');
                 JsonValue::Null
             },
-            _ => return $parser.unexpected_character(ch)
+            _ => return $parser.unexpected_character(ch, $expected)
         }
-    })
+    });
+
+    ($parser:ident) => (expect_value!{ $parser, VALUE })
 }
 
 impl<'a> Parser<'a> {
@@ -281,6 +398,10 @@ impl<'a> Parser<'a> {
             byte_ptr: source.as_ptr(),
             index: 0,
             length: source.len(),
+            depth: 0,
+            max_depth: None,
+            line: 1,
+            line_start: 0,
         }
     }
 
@@ -299,67 +420,156 @@ impl<'a> Parser<'a> {
         self.index += 1;
     }
 
-    fn source_position_from_index(&self, index: usize) -> Position {
-        let (bytes, _) = self.source.split_at(index-1);
+    // Moves past all the bytes ahead that can be part of a string verbatim,
+    // stops at the first quote, backslash or control character.
+    #[inline(always)]
+    fn skip_plain_string(&mut self) {
+        self.index += scan::find_special(&self.source.as_bytes()[self.index ..]);
+    }
+
+    // Must be called after bumping over a newline character.
+    #[inline(always)]
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.index;
+    }
 
+    // Only valid for indexes on the line the parser is currently at.
+    fn source_position_from_index(&self, index: usize) -> Position {
         Position {
-            line: bytes.lines().count(),
-            column: bytes.lines().last().map_or(1, |line| {
-                line.chars().count() + 1
-            })
+            line: self.line,
+            column: self.source[self.line_start .. index].chars().count() + 1,
         }
     }
 
-    fn unexpected_character<T: Sized>(&mut self, byte: u8) -> JsonResult<T> {
-        let pos = self.source_position_from_index(self.index);
-
-        let ch = if byte & 0x80 != 0 {
-            let mut buf = [byte,0,0,0];
-            let mut len = 0usize;
-
-            if byte & 0xE0 == 0xCE {
-                // 2 bytes, 11 bits
-                len = 2;
-                buf[1] = expect_byte!(self);
-            } else if byte & 0xF0 == 0xE0 {
-                // 3 bytes, 16 bits
-                len = 3;
-                buf[1] = expect_byte!(self);
-                buf[2] = expect_byte!(self);
-            } else if byte & 0xF8 == 0xF0 {
-                // 4 bytes, 21 bits
-                len = 4;
-                buf[1] = expect_byte!(self);
-                buf[2] = expect_byte!(self);
-                buf[3] = expect_byte!(self);
+    // Renders the path from the root to the value that was being parsed
+    // when an error occurred at `offset`, in the form of a normalized
+    // JSONPath, ie: `$['foo'][2]`.
+    //
+    // The path isn't tracked while parsing, since it's only needed for
+    // errors. Everything before `offset` has been parsed successfully, so
+    // the containers, keys and indexes leading to it are found by following
+    // the brackets, commas and strings in the source up to there.
+    fn current_path(&self, offset: usize, expected: Expected) -> String {
+        let bytes = &self.source.as_bytes()[.. offset];
+        let mut frames = Vec::new();
+        let mut index = 0;
+
+        // Whether the error is inside of the last value of the innermost
+        // container, rather than between its values
+        let mut in_value = !BETWEEN_VALUES.contains(&expected);
+
+        while index < bytes.len() {
+            match bytes[index] {
+                b'[' => frames.push(Frame::Array(0)),
+                b'{' => frames.push(Frame::Object(None)),
+                b']' | b'}' => { frames.pop(); },
+                b',' => match frames.last_mut() {
+                    Some(&mut Frame::Array(ref mut item)) => *item += 1,
+                    Some(&mut Frame::Object(ref mut key)) => *key = None,
+                    None                                  => {},
+                },
+                b'"' => {
+                    let start = index + 1;
+
+                    index = start;
+
+                    while index < bytes.len() && bytes[index] != b'"' {
+                        index += if bytes[index] == b'\\' { 2 } else { 1 };
+                    }
+
+                    let is_key = match frames.last_mut() {
+                        Some(&mut Frame::Object(ref mut key @ None)) => {
+                            *key = Some(start);
+                            true
+                        },
+                        _ => false,
+                    };
+
+                    // The error is inside of this string
+                    if index >= bytes.len() {
+                        in_value = !is_key;
+                        break;
+                    }
+                },
+                _ => {},
             }
 
-            let slice = try!(
-                str::from_utf8(&buf[0..len])
-                .map_err(|_| JsonError::FailedUtf8Parsing)
-            );
+            index += 1;
+        }
 
-            slice.chars().next().unwrap()
-        } else {
+        let mut path = String::from("$");
+        let last = frames.len();
+
+        for (level, frame) in frames.iter().enumerate() {
+            if level + 1 == last && !in_value {
+                break;
+            }
+
+            match *frame {
+                Frame::Array(item) => {
+                    path.push('[');
+                    path.push_str(&item.to_string());
+                    path.push(']');
+                },
+                Frame::Object(Some(start)) => {
+                    // Keys on the path have been parsed successfully before,
+                    // so reading them again can't fail.
+                    let mut parser = Parser::new(self.source);
+                    parser.index = start;
+
+                    let key = parser.read_string().unwrap_or_default();
 
-            // codepoints < 128 are safe ASCII compatibles
-            unsafe { char::from_u32_unchecked(byte as u32) }
+                    push_path_key(&mut path, &key);
+                },
+                Frame::Object(None) => {},
+            }
+        }
+
+        path
+    }
+
+    fn unexpected_end_of_json<T: Sized>(&mut self, expected: Expected) -> JsonResult<T> {
+        let pos = self.source_position_from_index(self.length);
+
+        Err(JsonError::UnexpectedEndOfJson {
+            line: pos.line,
+            column: pos.column,
+            offset: self.length,
+            path: self.current_path(self.length, expected),
+            expected: expected,
+        })
+    }
+
+    fn unexpected_character<T: Sized>(&mut self, byte: u8, expected: Expected) -> JsonResult<T> {
+        let offset = self.index - 1;
+        let pos = self.source_position_from_index(offset);
+
+        // Bytes above 127 can only be rejected at the start of a codepoint,
+        // since anything past it is consumed as a part of a string.
+        let ch = if byte & 0x80 != 0 && self.source.is_char_boundary(offset) {
+            self.source[offset ..].chars().next().unwrap()
+        } else {
+            char::from_u32(byte as u32).unwrap()
         };
 
         Err(JsonError::UnexpectedCharacter {
             ch: ch,
             line: pos.line,
             column: pos.column,
+            offset: offset,
+            path: self.current_path(offset, expected),
+            expected: expected,
         })
     }
 
     fn read_hexdec_digit(&mut self) -> JsonResult<u32> {
-        let ch = expect_byte!(self);
+        let ch = expect_byte!(self, HEX_DIGIT);
         Ok(match ch {
             b'0' ... b'9' => (ch - b'0'),
             b'a' ... b'f' => (ch + 10 - b'a'),
             b'A' ... b'F' => (ch + 10 - b'A'),
-            ch            => return self.unexpected_character(ch),
+            ch            => return self.unexpected_character(ch, HEX_DIGIT),
         } as u32)
     }
 
@@ -381,7 +591,7 @@ impl<'a> Parser<'a> {
                 codepoint -= 0xD800;
                 codepoint <<= 10;
 
-                sequence!(self, b'\\', b'u');
+                sequence!(self, LOW_SURROGATE, b'\\', b'u');
 
                 let lower = try!(self.read_hexdec_codepoint());
 
@@ -426,18 +636,22 @@ impl<'a> Parser<'a> {
 
         loop {
             if ALLOWED[ch as usize] {
-                buffer.push(ch);
-                ch = expect_byte!(self);
+                let run = self.index - 1;
+
+                self.skip_plain_string();
+                buffer.extend_from_slice(&self.source.as_bytes()[run .. self.index]);
+
+                ch = expect_byte!(self, CLOSING_QUOTE);
                 continue;
             }
             match ch {
                 b'"'  => break,
                 b'\\' => {
-                    let escaped = expect_byte!(self);
+                    let escaped = expect_byte!(self, ESCAPE);
                     let escaped = match escaped {
                         b'u'  => {
                             try!(self.read_codepoint(&mut buffer));
-                            ch = expect_byte!(self);
+                            ch = expect_byte!(self, CLOSING_QUOTE);
                             continue;
                         },
                         b'"'  |
@@ -448,13 +662,13 @@ impl<'a> Parser<'a> {
                         b't'  => b'\t',
                         b'r'  => b'\r',
                         b'n'  => b'\n',
-                        _     => return self.unexpected_character(escaped)
+                        _     => return self.unexpected_character(escaped, ESCAPE)
                     };
                     buffer.push(escaped);
                 },
-                _ => return self.unexpected_character(ch)
+                _ => return self.unexpected_character(ch, NOTHING)
             }
-            ch = expect_byte!(self);
+            ch = expect_byte!(self, CLOSING_QUOTE);
         }
 
         // Since the original source is already valid UTF-8, and `\`
@@ -462,11 +676,65 @@ impl<'a> Parser<'a> {
         Ok(unsafe { String::from_utf8_unchecked(buffer) })
     }
 
-    fn read_big_number(&mut self, num: u64) -> JsonResult<f64> {
+    // Negative zero can't be an `Integer`, so `-0` is a float
+    fn read_zero(&mut self, negative: bool) -> JsonResult<JsonValue> {
+        if !self.is_eof() {
+            match self.read_byte() {
+                b'.' | b'e' | b'E' => {
+                    let num = try!(self.read_number_with_fraction(0, 0));
+                    return Ok(make_number(num, negative));
+                },
+                _ => {}
+            }
+        }
+
+        Ok(if negative {
+            JsonValue::Number(-0.0)
+        } else {
+            JsonValue::Integer(Integer::from(0))
+        })
+    }
+
+    // Continues reading an integer that overflows u64, in u128, or as float
+    // if it either overflows u128 or turns out to have a fraction.
+    fn read_big_integer(&mut self, num: u64, negative: bool) -> JsonResult<JsonValue> {
+        let mut num = num as u128;
+
+        loop {
+            if self.is_eof() {
+                break;
+            }
+
+            let ch = self.read_byte();
+
+            match ch {
+                b'0' ... b'9' => {
+                    let digit = (ch - b'0') as u128;
+
+                    if num > (u128::MAX - digit) / 10 {
+                        let (num, e) = shorten(num);
+                        return Ok(make_number(try!(self.read_big_number(num, e)), negative));
+                    }
+
+                    self.bump();
+                    num = num * 10 + digit;
+                },
+                b'.' | b'e' | b'E' => {
+                    // Shortened to at least 19 digits, so that digits of the
+                    // fraction are past `MAX_PRECISION` and ignored
+                    let (num, e) = shorten(num);
+                    return Ok(make_number(try!(self.read_number_with_fraction(num, e)), negative));
+                },
+                _ => break
+            }
+        }
+
+        Ok(make_big_integer(num, negative))
+    }
+
+    fn read_big_number(&mut self, num: u64, mut e: i32) -> JsonResult<f64> {
         // Attempt to continue reading digits that would overflow
         // u64 into freshly converted f64
-
-        let mut e = 0i32;
         loop {
             if self.is_eof() {
                 return Ok(make_float(num, e));
@@ -514,14 +782,14 @@ impl<'a> Parser<'a> {
 
         if ch == b'e' || ch == b'E' {
             self.bump();
-            ch = expect_byte!(self);
+            ch = expect_byte!(self, DIGIT);
             let sign = match ch {
                 b'-' => {
-                    ch = expect_byte!(self);
+                    ch = expect_byte!(self, DIGIT);
                     -1
                 },
                 b'+' => {
-                    ch = expect_byte!(self);
+                    ch = expect_byte!(self, DIGIT);
                     1
                 },
                 _    => 1
@@ -531,7 +799,7 @@ impl<'a> Parser<'a> {
 
             let mut e = match ch {
                 b'0' ... b'9' => (ch - b'0') as i32,
-                _ => return self.unexpected_character(ch),
+                _ => return self.unexpected_character(ch, DIGIT),
             };
 
             read_num!(self, digit, e = (e << 3) + (e << 1) + digit as i32);
@@ -542,43 +810,64 @@ impl<'a> Parser<'a> {
         Ok(make_float(num, e))
     }
 
-    fn read_object(&mut self) -> JsonResult<BTreeMap<String, JsonValue>> {
-        let mut object = BTreeMap::new();
+    fn check_depth(&self) -> JsonResult<()> {
+        match self.max_depth {
+            Some(max) if self.depth >= max => Err(JsonError::ExceededDepthLimit),
+            _                              => Ok(()),
+        }
+    }
+
+    fn read_object(&mut self) -> JsonResult<Object> {
+        try!(self.check_depth());
 
-        let key = expect!{ self,
+        let mut object = Object::new();
+
+        let key = expect!{ self, KEY_OR_OBJECT_END,
             b'}'  => return Ok(object),
             b'\"' => expect_string!(self)
         };
 
-        expect!(self, b':');
+        expect!(self, b':', COLON);
 
+        self.depth += 1;
         object.insert(key, expect_value!(self));
 
         loop {
-            let key = expect!{ self,
+            let key = expect!{ self, OBJECT_NEXT,
                 b'}' => break,
                 b',' => {
-                    expect!(self, b'"');
+                    expect!(self, b'"', KEY);
                     expect_string!(self)
                 }
             };
 
-            expect!(self, b':');
+            expect!(self, b':', COLON);
 
             object.insert(key, expect_value!(self));
         }
 
+        self.depth -= 1;
+
         Ok(object)
     }
 
     fn read_array(&mut self) -> JsonResult<Vec<JsonValue>> {
-        let first = expect_value!{ self, b']' => return Ok(Vec::new()) };
+        try!(self.check_depth());
+
+        self.depth += 1;
+
+        let first = expect_value!{ self, VALUE_OR_ARRAY_END,
+            b']' => {
+                self.depth -= 1;
+                return Ok(Vec::new());
+            }
+        };
 
         let mut array = Vec::with_capacity(20);
         array.push(first);
 
         loop {
-            expect!{ self,
+            expect!{ self, ARRAY_NEXT,
                 b']' => break,
                 b',' => {
                     let value = expect_value!(self);
@@ -587,16 +876,22 @@ impl<'a> Parser<'a> {
             };
         }
 
+        self.depth -= 1;
+
         Ok(array)
     }
 
     fn ensure_end(&mut self) -> JsonResult<()> {
         while !self.is_eof() {
             match self.read_byte() {
+                b'\n'         => {
+                    self.bump();
+                    self.new_line();
+                },
                 9 ... 13 | 32 => self.bump(),
                 ch            => {
                     self.bump();
-                    return self.unexpected_character(ch);
+                    return self.unexpected_character(ch, END_OF_JSON);
                 }
             }
         }
@@ -604,13 +899,22 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    fn read_string(&mut self) -> JsonResult<String> {
+        Ok(expect_string!(self))
+    }
+
     fn value(&mut self) -> JsonResult<JsonValue> {
         Ok(expect_value!(self))
     }
 }
 
 pub fn parse(source: &str) -> JsonResult<JsonValue> {
+    parse_with(source, ParseOptions::new())
+}
+
+pub fn parse_with(source: &str, options: ParseOptions) -> JsonResult<JsonValue> {
     let mut parser = Parser::new(source);
+    parser.max_depth = options.max_depth;
 
     let value = try!(parser.value());
 
@@ -0,0 +1,267 @@
+use std::mem;
+use pointer;
+use { JsonValue, JsonResult, JsonError, Object };
+
+/// Applies a JSON Patch, as defined in RFC 6902, to the value. The patch is
+/// an array of `add`, `remove`, `replace`, `move`, `copy` and `test`
+/// operations, applied one after another.
+///
+/// Patches are atomic: if any of the operations fails, the value is left
+/// as it was and `JsonError::PatchFailed` tells which operation failed and
+/// why. Each applied operation records how to undo it, keeping only the
+/// values it removed or replaced, and a failed patch is rolled back by
+/// undoing them in reverse, so the value is never copied as a whole.
+///
+/// ```
+/// # #[macro_use] extern crate json;
+/// # fn main() {
+/// let mut data = object!{ "name" => "foo", "tags" => array!["a"] };
+///
+/// let patch = json::parse(r#"[
+///     { "op": "replace", "path": "/name", "value": "bar" },
+///     { "op": "add", "path": "/tags/-", "value": "b" }
+/// ]"#).unwrap();
+///
+/// json::apply_patch(&mut data, &patch).unwrap();
+///
+/// assert_eq!(data, object!{ "name" => "bar", "tags" => array!["a", "b"] });
+///
+/// let patch = json::parse(r#"[
+///     { "op": "remove", "path": "/name" },
+///     { "op": "test", "path": "/tags/0", "value": "c" }
+/// ]"#).unwrap();
+///
+/// let err = json::apply_patch(&mut data, &patch).unwrap_err();
+///
+/// assert_eq!(err.to_string(), "JSON Patch operation 1 (test) failed: Test failed, different value at: /tags/0");
+/// assert!(data["name"] == "bar");
+/// # }
+/// ```
+pub fn apply_patch(target: &mut JsonValue, patch: &JsonValue) -> JsonResult<()> {
+    let operations = match *patch {
+        JsonValue::Array(ref operations) => operations,
+        _ => return Err(JsonError::InvalidPatch("Patch must be an array of operations")),
+    };
+
+    let mut log = Vec::new();
+
+    for (index, operation) in operations.iter().enumerate() {
+        if let Err(err) = apply(target, operation, &mut log) {
+            rollback(target, log);
+
+            return Err(JsonError::PatchFailed {
+                index: index,
+                op: operation["op"].as_str().map(Into::into),
+                reason: Box::new(err),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Inverse of an applied operation. Pointers are the ones valid right after
+// the operation, with `-` resolved to the index the value was appended at.
+enum Undo {
+    // Takes out the added value, putting back the one it replaced, if any.
+    Add {
+        pointer: String,
+        replaced: Option<JsonValue>,
+    },
+    // Puts a removed value back, at the position it had in its object.
+    Remove {
+        pointer: String,
+        value: JsonValue,
+        position: Option<usize>,
+    },
+    Replace {
+        pointer: String,
+        value: JsonValue,
+    },
+    // Undoes the add, then the remove the move is made of.
+    Move {
+        pointer: String,
+        replaced: Option<JsonValue>,
+        from: String,
+        position: Option<usize>,
+    },
+}
+
+fn rollback(target: &mut JsonValue, log: Vec<Undo>) {
+    for undo in log.into_iter().rev() {
+        match undo {
+            Undo::Add { pointer, replaced } => {
+                take_back(target, &pointer, replaced);
+            },
+            Undo::Remove { pointer, value, position } => {
+                put_back(target, &pointer, value, position);
+            },
+            Undo::Replace { pointer, value } => {
+                *target.pointer_mut(&pointer).expect("Undo pointer must exist") = value;
+            },
+            Undo::Move { pointer, replaced, from, position } => {
+                let value = take_back(target, &pointer, replaced);
+
+                put_back(target, &from, value, position);
+            },
+        }
+    }
+}
+
+fn take_back(target: &mut JsonValue, pointer: &str, replaced: Option<JsonValue>) -> JsonValue {
+    match replaced {
+        Some(replaced) => target.pointer_insert(pointer, replaced).ok().and_then(|value| value),
+        None           => target.pointer_remove(pointer).ok(),
+    }.expect("Undo pointer must exist")
+}
+
+fn put_back(target: &mut JsonValue, pointer: &str, value: JsonValue, position: Option<usize>) {
+    if let Some(position) = position {
+        let (parent, key) = split_parent(pointer);
+
+        if let Ok(&mut JsonValue::Object(ref mut object)) = target.pointer_mut(parent) {
+            object.insert_at(position, key, value);
+            return;
+        }
+    }
+
+    target.pointer_insert(pointer, value).expect("Undo pointer must exist");
+}
+
+// Splits a valid, non-empty pointer into the pointer to its parent and the
+// unescaped last token.
+fn split_parent(pointer: &str) -> (&str, String) {
+    let slash = pointer.rfind('/').expect("Pointer must not be empty");
+    let key = pointer::parse(&pointer[slash ..]).expect("Pointer must be valid").remove(0);
+
+    (&pointer[.. slash], key)
+}
+
+// Position of the value among the entries of the object containing it,
+// when the object keeps an order of its own.
+fn position(target: &JsonValue, pointer: &str) -> Option<usize> {
+    if pointer.is_empty() {
+        return None;
+    }
+
+    let (parent, key) = split_parent(pointer);
+
+    match target.pointer(parent) {
+        Ok(parent) => match *parent {
+            JsonValue::Object(ref object) => object.position(&key),
+            _                             => None,
+        },
+        Err(_) => None,
+    }
+}
+
+// Resolves a trailing `-` to the index of the value just appended with it.
+fn resolve(target: &JsonValue, pointer: &str) -> String {
+    let parent = match pointer.strip_suffix("/-") {
+        Some(parent) => parent,
+        None         => return pointer.into(),
+    };
+
+    match target.pointer(parent) {
+        Ok(value) => match *value {
+            JsonValue::Array(ref vec) => format!("{}/{}", parent, vec.len() - 1),
+            _                         => pointer.into(),
+        },
+        Err(_) => pointer.into(),
+    }
+}
+
+fn apply(target: &mut JsonValue, operation: &JsonValue, log: &mut Vec<Undo>) -> JsonResult<()> {
+    let operation = match *operation {
+        JsonValue::Object(ref operation) => operation,
+        _ => return Err(JsonError::InvalidPatch("Operation must be an object")),
+    };
+
+    let path = try!(member_str(operation, "path"));
+
+    match try!(member_str(operation, "op")) {
+        "add" => {
+            let replaced = try!(target.pointer_insert(path, try!(member(operation, "value")).clone()));
+
+            log.push(Undo::Add {
+                pointer: resolve(target, path),
+                replaced: replaced,
+            });
+        },
+        "remove" => {
+            let position = position(target, path);
+            let value = try!(target.pointer_remove(path));
+
+            log.push(Undo::Remove {
+                pointer: path.into(),
+                value: value,
+                position: position,
+            });
+        },
+        "replace" => {
+            let value = try!(member(operation, "value")).clone();
+            let value = mem::replace(try!(target.pointer_mut(path)), value);
+
+            log.push(Undo::Replace {
+                pointer: path.into(),
+                value: value,
+            });
+        },
+        "move" => {
+            let from = try!(member_str(operation, "from"));
+
+            // A value can't be moved into one of its own children
+            if path.starts_with(from) && path[from.len() ..].starts_with('/') {
+                return Err(JsonError::InvalidPatch("Can't move a value into itself"));
+            }
+
+            let position = position(target, from);
+            let value = try!(target.pointer_remove(from));
+
+            match pointer::try_insert(target, path, value) {
+                Ok(replaced) => log.push(Undo::Move {
+                    pointer: resolve(target, path),
+                    replaced: replaced,
+                    from: from.into(),
+                    position: position,
+                }),
+                Err((err, value)) => {
+                    put_back(target, from, value, position);
+
+                    return Err(err);
+                },
+            }
+        },
+        "copy" => {
+            let value = try!(target.pointer(try!(member_str(operation, "from")))).clone();
+            let replaced = try!(target.pointer_insert(path, value));
+
+            log.push(Undo::Add {
+                pointer: resolve(target, path),
+                replaced: replaced,
+            });
+        },
+        "test" => {
+            if *try!(target.pointer(path)) != *try!(member(operation, "value")) {
+                return Err(JsonError::TestFailed(path.into()));
+            }
+        },
+        _ => return Err(JsonError::InvalidPatch("Unknown operation")),
+    }
+
+    Ok(())
+}
+
+fn member<'a>(operation: &'a Object, key: &'static str) -> JsonResult<&'a JsonValue> {
+    operation.get(key).ok_or(JsonError::InvalidPatchMember {
+        member: key,
+        reason: "is missing",
+    })
+}
+
+fn member_str<'a>(operation: &'a Object, key: &'static str) -> JsonResult<&'a str> {
+    try!(member(operation, key)).as_str().ok_or(JsonError::InvalidPatchMember {
+        member: key,
+        reason: "must be a string",
+    })
+}
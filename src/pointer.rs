@@ -0,0 +1,164 @@
+use std::mem;
+use { JsonValue, JsonResult, JsonError };
+
+// Splits a JSON Pointer into its reference tokens, with `~1` and `~0`
+// unescaped. The whole pointer is checked before anything is looked up,
+// so that a bad pointer never leaves a value half modified.
+pub fn parse(pointer: &str) -> JsonResult<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if !pointer.starts_with('/') {
+        return Err(JsonError::InvalidPointer(pointer.into()));
+    }
+
+    pointer[1..].split('/').map(|token| unescape(pointer, token)).collect()
+}
+
+fn unescape(pointer: &str, token: &str) -> JsonResult<String> {
+    let mut key = String::with_capacity(token.len());
+    let mut chars = token.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '~' {
+            key.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('0') => key.push('~'),
+            Some('1') => key.push('/'),
+            _         => return Err(JsonError::InvalidPointer(pointer.into())),
+        }
+    }
+
+    Ok(key)
+}
+
+// Array indexes are plain decimal numbers, without a sign or leading zeros.
+fn index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+
+    if !token.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    token.parse().ok()
+}
+
+fn not_found(pointer: &str, token: &str) -> JsonError {
+    JsonError::PointerNotFound {
+        pointer: pointer.into(),
+        segment: token.into(),
+    }
+}
+
+fn child<'a>(value: &'a JsonValue, token: &str) -> Option<&'a JsonValue> {
+    match *value {
+        JsonValue::Object(ref object) => object.get(token),
+        JsonValue::Array(ref vec)     => index(token).and_then(|index| vec.get(index)),
+        _                             => None,
+    }
+}
+
+fn child_mut<'a>(value: &'a mut JsonValue, token: &str) -> Option<&'a mut JsonValue> {
+    match *value {
+        JsonValue::Object(ref mut object) => object.get_mut(token),
+        JsonValue::Array(ref mut vec)     => index(token).and_then(move |index| vec.get_mut(index)),
+        _                                 => None,
+    }
+}
+
+pub fn get<'a>(root: &'a JsonValue, pointer: &str) -> JsonResult<&'a JsonValue> {
+    let mut value = root;
+
+    for token in try!(parse(pointer)) {
+        value = match child(value, &token) {
+            Some(value) => value,
+            None        => return Err(not_found(pointer, &token)),
+        };
+    }
+
+    Ok(value)
+}
+
+fn follow_mut<'a>(root: &'a mut JsonValue, pointer: &str, tokens: &[String]) -> JsonResult<&'a mut JsonValue> {
+    let mut value = root;
+
+    for token in tokens {
+        value = match child_mut(value, token) {
+            Some(value) => value,
+            None        => return Err(not_found(pointer, token)),
+        };
+    }
+
+    Ok(value)
+}
+
+pub fn get_mut<'a>(root: &'a mut JsonValue, pointer: &str) -> JsonResult<&'a mut JsonValue> {
+    let tokens = try!(parse(pointer));
+
+    follow_mut(root, pointer, &tokens)
+}
+
+pub fn insert(root: &mut JsonValue, pointer: &str, value: JsonValue) -> JsonResult<Option<JsonValue>> {
+    try_insert(root, pointer, value).map_err(|(err, _)| err)
+}
+
+// Same as `insert`, but hands the value back when it can't be inserted,
+// so that a value taken out of the tree can be put back where it was.
+pub fn try_insert(root: &mut JsonValue, pointer: &str, value: JsonValue) -> Result<Option<JsonValue>, (JsonError, JsonValue)> {
+    let tokens = match parse(pointer) {
+        Ok(tokens) => tokens,
+        Err(err)   => return Err((err, value)),
+    };
+
+    let (last, parents) = match tokens.split_last() {
+        Some(split) => split,
+        None        => return Ok(Some(mem::replace(root, value))),
+    };
+
+    let parent = match follow_mut(root, pointer, parents) {
+        Ok(parent) => parent,
+        Err(err)   => return Err((err, value)),
+    };
+
+    match *parent {
+        JsonValue::Object(ref mut object) => Ok(object.insert(last.clone(), value)),
+        JsonValue::Array(ref mut vec) => {
+            let index = match index(last) {
+                Some(index) if index <= vec.len() => index,
+                _ if last == "-"                  => vec.len(),
+                _                                 => return Err((not_found(pointer, last), value)),
+            };
+
+            vec.insert(index, value);
+
+            Ok(None)
+        },
+        _ => Err((not_found(pointer, last), value)),
+    }
+}
+
+pub fn remove(root: &mut JsonValue, pointer: &str) -> JsonResult<JsonValue> {
+    let tokens = try!(parse(pointer));
+
+    let (last, parents) = match tokens.split_last() {
+        Some(split) => split,
+        None        => return Ok(root.take()),
+    };
+
+    let removed = match *try!(follow_mut(root, pointer, parents)) {
+        JsonValue::Object(ref mut object) => object.remove(last),
+        JsonValue::Array(ref mut vec) => match index(last) {
+            Some(index) if index < vec.len() => Some(vec.remove(index)),
+            _                                => None,
+        },
+        _ => None,
+    };
+
+    removed.ok_or_else(|| not_found(pointer, last))
+}
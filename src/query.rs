@@ -0,0 +1,1492 @@
+//! JSONPath queries, as defined in RFC 9535.
+//!
+//! A query is compiled into a `JsonPath` once, and can then be run against
+//! any number of values. Running it returns the selected `Node`s, each with
+//! a reference to the value and its normalized path.
+//!
+//! ```
+//! # #[macro_use] extern crate json;
+//! # fn main() {
+//! use json::JsonPath;
+//!
+//! let store = object!{
+//!     "books" => array![
+//!         object!{ "title" => "Sayings of the Century", "price" => 8.95 },
+//!         object!{ "title" => "Sword of Honour", "price" => 12.99 },
+//!         object!{ "title" => "Moby Dick", "price" => 8.99 }
+//!     ]
+//! };
+//!
+//! let cheap = JsonPath::compile("$.books[?@.price < 10].title").unwrap();
+//! let nodes = cheap.query(&store);
+//!
+//! assert_eq!(nodes.len(), 2);
+//! assert!(*nodes[0].value() == "Sayings of the Century");
+//! assert_eq!(nodes[1].path(), "$['books'][2]['title']");
+//! # }
+//! ```
+//!
+//! All of RFC 9535 is supported: name, wildcard, index, slice and filter
+//! selectors, unions of them, descendant segments, and the `length`,
+//! `count`, `value`, `match` and `search` functions. The last two need the
+//! `regex` feature, without it queries using them fail to compile.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use error::push_path_key;
+use { JsonValue, JsonResult, JsonError, parse };
+
+// Indexes and slice bounds have to be within the I-JSON range of integers
+const MAX_INT: i64 = 9007199254740991;
+
+// How deep filters, parentheses and function calls can be nested
+const MAX_DEPTH: usize = 64;
+
+/// Compiled JSONPath query.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+/// Value selected by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node<'a> {
+    value: &'a JsonValue,
+    path: String,
+}
+
+impl JsonPath {
+    /// Compiles the query, failing with `JsonError::InvalidQuery` if it
+    /// isn't a valid JSONPath.
+    pub fn compile(query: &str) -> JsonResult<JsonPath> {
+        let mut parser = Parser {
+            source: query,
+            index: 0,
+            depth: 0,
+        };
+
+        if !parser.eat(b'$') {
+            return parser.error("Query must start with `$`");
+        }
+
+        let segments = try!(parser.segments());
+
+        if parser.index < query.len() {
+            return parser.error("Unexpected character");
+        }
+
+        Ok(JsonPath {
+            segments: segments,
+        })
+    }
+
+    /// Runs the query, returning the selected nodes in the order RFC 9535
+    /// defines. The same node can be selected more than once.
+    pub fn query<'a>(&self, value: &'a JsonValue) -> Vec<Node<'a>> {
+        let root = Node {
+            value: value,
+            path: "$".into(),
+        };
+
+        let context = Context {
+            root: value,
+            patterns: RefCell::new(HashMap::new()),
+        };
+
+        select(&self.segments, &context, root)
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = JsonError;
+
+    fn from_str(query: &str) -> JsonResult<JsonPath> {
+        JsonPath::compile(query)
+    }
+}
+
+impl<'a> Node<'a> {
+    pub fn value(&self) -> &'a JsonValue {
+        self.value
+    }
+
+    /// Normalized path of the node, ie: `$['books'][2]['title']`, which
+    /// is unique to it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn key(&self, key: &str, value: &'a JsonValue) -> Node<'a> {
+        let mut path = self.path.clone();
+
+        push_path_key(&mut path, key);
+
+        Node {
+            value: value,
+            path: path,
+        }
+    }
+
+    fn index(&self, index: usize, value: &'a JsonValue) -> Node<'a> {
+        Node {
+            value: value,
+            path: format!("{}[{}]", self.path, index),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>),
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(Expr),
+}
+
+// Query inside of a filter, starting at either `$` or `@`
+#[derive(Debug, Clone)]
+struct Query {
+    absolute: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Compare(Comparable, Operator, Comparable),
+    Exists(Query),
+    Call(Function),
+}
+
+#[derive(Debug, Clone)]
+enum Comparable {
+    Literal(JsonValue),
+    Query(Query),
+    Call(Function),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone)]
+enum Function {
+    Length(Box<Comparable>),
+    Count(Query),
+    Value(Query),
+
+    // `match` when `full` is set, `search` otherwise. The pattern is
+    // compiled upfront when it's a literal.
+    Regex {
+        full: bool,
+        value: Box<Comparable>,
+        pattern: Box<Comparable>,
+        compiled: Option<regexp::Pattern>,
+    },
+}
+
+// Argument of a function, before it's checked against the type of the
+// parameter. None of the functions take logical expressions, so those are
+// only parsed to report errors in them.
+enum Argument {
+    Value(Comparable),
+    Logical,
+}
+
+impl Query {
+    // Singular queries select at most one node, only those can be compared
+    fn is_singular(&self) -> bool {
+        self.segments.iter().all(|segment| match *segment {
+            Segment::Child(ref selectors) => selectors.len() == 1 && match selectors[0] {
+                Selector::Name(_) | Selector::Index(_) => true,
+                _                                      => false,
+            },
+            Segment::Descendant(_) => false,
+        })
+    }
+}
+
+impl Function {
+    fn is_logical(&self) -> bool {
+        match *self {
+            Function::Regex { .. } => true,
+            _                      => false,
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    index: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, reason: &'static str) -> JsonResult<T> {
+        self.error_at(self.index, reason)
+    }
+
+    fn error_at<T>(&self, offset: usize, reason: &'static str) -> JsonResult<T> {
+        Err(JsonError::InvalidQuery {
+            query: self.source.into(),
+            offset: offset,
+            reason: reason,
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.index).cloned()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.index += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn eat_str(&mut self, string: &str) -> bool {
+        if self.source[self.index ..].starts_with(string) {
+            self.index += string.len();
+            return true;
+        }
+
+        false
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.index += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.index;
+
+        while let Some(b'0' ... b'9') = self.peek() {
+            self.index += 1;
+        }
+
+        self.index - start
+    }
+
+    fn segments(&mut self) -> JsonResult<Vec<Segment>> {
+        let mut segments = Vec::new();
+
+        loop {
+            let start = self.index;
+
+            self.skip_whitespace();
+
+            if self.peek() == Some(b'[') {
+                segments.push(Segment::Child(try!(self.bracketed())));
+                continue;
+            }
+
+            if !self.eat(b'.') {
+                // Whitespace is only allowed in between segments
+                self.index = start;
+                return Ok(segments);
+            }
+
+            if self.eat(b'.') {
+                let selectors = match self.peek() {
+                    Some(b'[') => try!(self.bracketed()),
+                    _          => vec![try!(self.shorthand())],
+                };
+
+                segments.push(Segment::Descendant(selectors));
+            } else {
+                segments.push(Segment::Child(vec![try!(self.shorthand())]));
+            }
+        }
+    }
+
+    // Wildcard or member name following a dot
+    fn shorthand(&mut self) -> JsonResult<Selector> {
+        if self.eat(b'*') {
+            return Ok(Selector::Wildcard);
+        }
+
+        let start = self.index;
+
+        for (offset, ch) in self.source[start ..].char_indices() {
+            let valid = match ch {
+                'a' ... 'z' | 'A' ... 'Z' | '_' => true,
+                '0' ... '9'                     => offset > 0,
+                _                               => ch > '\u{7f}',
+            };
+
+            if !valid {
+                break;
+            }
+
+            self.index = start + offset + ch.len_utf8();
+        }
+
+        if self.index == start {
+            return self.error("Expected a member name or `*`");
+        }
+
+        Ok(Selector::Name(self.source[start .. self.index].into()))
+    }
+
+    fn bracketed(&mut self) -> JsonResult<Vec<Selector>> {
+        let mut selectors = Vec::new();
+
+        self.index += 1;
+
+        loop {
+            self.skip_whitespace();
+            selectors.push(try!(self.selector()));
+            self.skip_whitespace();
+
+            if self.eat(b']') {
+                return Ok(selectors);
+            }
+
+            if !self.eat(b',') {
+                return self.error("Expected `,` or `]`");
+            }
+        }
+    }
+
+    fn selector(&mut self) -> JsonResult<Selector> {
+        match self.peek() {
+            Some(b'\'') | Some(b'"') => Ok(Selector::Name(try!(self.string()))),
+            Some(b'-') | Some(b'0' ... b'9') | Some(b':') => self.index_or_slice(),
+            Some(b'*') => {
+                self.index += 1;
+
+                Ok(Selector::Wildcard)
+            },
+            Some(b'?') => {
+                self.index += 1;
+                self.skip_whitespace();
+
+                Ok(Selector::Filter(try!(self.logical())))
+            },
+            _ => self.error("Expected a selector"),
+        }
+    }
+
+    fn index_or_slice(&mut self) -> JsonResult<Selector> {
+        let start = try!(self.optional_int());
+
+        self.skip_whitespace();
+
+        if !self.eat(b':') {
+            return match start {
+                Some(index) => Ok(Selector::Index(index)),
+                None        => self.error("Expected an index"),
+            };
+        }
+
+        self.skip_whitespace();
+
+        let end = try!(self.optional_int());
+
+        self.skip_whitespace();
+
+        let step = if self.eat(b':') {
+            self.skip_whitespace();
+            try!(self.optional_int())
+        } else {
+            None
+        };
+
+        Ok(Selector::Slice(start, end, step))
+    }
+
+    fn optional_int(&mut self) -> JsonResult<Option<i64>> {
+        match self.peek() {
+            Some(b'-') | Some(b'0' ... b'9') => self.int().map(Some),
+            _                                => Ok(None),
+        }
+    }
+
+    fn int(&mut self) -> JsonResult<i64> {
+        let start = self.index;
+        let negative = self.eat(b'-');
+
+        match self.peek() {
+            Some(b'0') if negative => return self.error("Index can't be `-0`"),
+            Some(b'0') => {
+                self.index += 1;
+
+                if self.skip_digits() > 0 {
+                    return self.error_at(start, "Index can't have leading zeros");
+                }
+            },
+            Some(b'1' ... b'9') => {
+                self.skip_digits();
+            },
+            _ => return self.error("Expected a digit"),
+        }
+
+        match self.source[start .. self.index].parse::<i64>() {
+            Ok(num) if (-MAX_INT .. MAX_INT + 1).contains(&num) => Ok(num),
+            _ => self.error_at(start, "Index out of range"),
+        }
+    }
+
+    fn string(&mut self) -> JsonResult<String> {
+        let quote = self.source.as_bytes()[self.index] as char;
+        let mut string = String::new();
+
+        self.index += 1;
+
+        loop {
+            let ch = match self.source[self.index ..].chars().next() {
+                Some(ch) => ch,
+                None     => return self.error("Unterminated string"),
+            };
+
+            if ch == quote {
+                self.index += 1;
+                return Ok(string);
+            }
+
+            match ch {
+                '\\' => {
+                    self.index += 1;
+                    string.push(try!(self.escape(quote)));
+                },
+                '\u{0}' ... '\u{1f}' => return self.error("Unescaped control character"),
+                _ => {
+                    string.push(ch);
+                    self.index += ch.len_utf8();
+                },
+            }
+        }
+    }
+
+    fn escape(&mut self, quote: char) -> JsonResult<char> {
+        let ch = match self.peek() {
+            Some(b'b')  => '\u{8}',
+            Some(b'f')  => '\u{c}',
+            Some(b'n')  => '\n',
+            Some(b'r')  => '\r',
+            Some(b't')  => '\t',
+            Some(b'/')  => '/',
+            Some(b'\\') => '\\',
+            Some(b'u')  => {
+                self.index += 1;
+                return self.unicode_escape();
+            },
+            Some(byte) if byte as char == quote => quote,
+            _ => return self.error("Invalid escape sequence"),
+        };
+
+        self.index += 1;
+
+        Ok(ch)
+    }
+
+    fn hex(&mut self) -> JsonResult<u32> {
+        let digits = self.source.get(self.index .. self.index + 4).unwrap_or("");
+
+        if digits.len() < 4 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return self.error("Expected 4 hexadecimal digits");
+        }
+
+        self.index += 4;
+
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn unicode_escape(&mut self) -> JsonResult<char> {
+        let start = self.index;
+        let mut code = try!(self.hex());
+
+        match code {
+            0xD800 ... 0xDBFF => {
+                if !self.eat_str("\\u") {
+                    return self.error_at(start, "Lone surrogate");
+                }
+
+                let low = try!(self.hex());
+
+                if !(0xDC00 .. 0xE000).contains(&low) {
+                    return self.error_at(start, "Lone surrogate");
+                }
+
+                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+            },
+            0xDC00 ... 0xDFFF => return self.error_at(start, "Lone surrogate"),
+            _ => {},
+        }
+
+        Ok(::std::char::from_u32(code).unwrap())
+    }
+
+    fn enter(&mut self) -> JsonResult<()> {
+        if self.depth == MAX_DEPTH {
+            return self.error("Query nested too deep");
+        }
+
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    fn logical(&mut self) -> JsonResult<Expr> {
+        try!(self.enter());
+
+        let expr = self.or();
+
+        self.depth -= 1;
+
+        expr
+    }
+
+    fn or(&mut self) -> JsonResult<Expr> {
+        let first = try!(self.and());
+
+        self.or_rest(first)
+    }
+
+    // Rest of a logical expression, after its first operand
+    fn or_rest(&mut self, first: Expr) -> JsonResult<Expr> {
+        let mut exprs = vec![first];
+
+        loop {
+            let start = self.index;
+
+            self.skip_whitespace();
+
+            if !self.eat_str("||") {
+                self.index = start;
+                break;
+            }
+
+            self.skip_whitespace();
+            exprs.push(try!(self.and()));
+        }
+
+        Ok(if exprs.len() == 1 { exprs.pop().unwrap() } else { Expr::Or(exprs) })
+    }
+
+    fn and(&mut self) -> JsonResult<Expr> {
+        let first = try!(self.basic());
+
+        self.and_rest(first)
+    }
+
+    fn and_rest(&mut self, first: Expr) -> JsonResult<Expr> {
+        let mut exprs = vec![first];
+
+        loop {
+            let start = self.index;
+
+            self.skip_whitespace();
+
+            if !self.eat_str("&&") {
+                self.index = start;
+                break;
+            }
+
+            self.skip_whitespace();
+            exprs.push(try!(self.basic()));
+        }
+
+        Ok(if exprs.len() == 1 { exprs.pop().unwrap() } else { Expr::And(exprs) })
+    }
+
+    fn basic(&mut self) -> JsonResult<Expr> {
+        if self.eat(b'!') {
+            self.skip_whitespace();
+
+            let start = self.index;
+            let expr = if self.eat(b'(') {
+                try!(self.parenthesized())
+            } else {
+                let operand = try!(self.comparable());
+                try!(self.test(operand, start))
+            };
+
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+
+        if self.eat(b'(') {
+            return self.parenthesized();
+        }
+
+        let start = self.index;
+        let left = try!(self.comparable());
+
+        self.compare_or_test(left, start)
+    }
+
+    // Comparison with `left` on the left side, or `left` on its own
+    fn compare_or_test(&mut self, left: Comparable, start: usize) -> JsonResult<Expr> {
+        let end = self.index;
+
+        self.skip_whitespace();
+
+        let operator = match self.operator() {
+            Some(operator) => operator,
+            None => {
+                self.index = end;
+                return self.test(left, start);
+            }
+        };
+
+        try!(self.check_comparable(&left, start));
+        self.skip_whitespace();
+
+        let start = self.index;
+        let right = try!(self.comparable());
+
+        try!(self.check_comparable(&right, start));
+
+        Ok(Expr::Compare(left, operator, right))
+    }
+
+    fn parenthesized(&mut self) -> JsonResult<Expr> {
+        self.skip_whitespace();
+
+        let expr = try!(self.logical());
+
+        self.skip_whitespace();
+
+        if !self.eat(b')') {
+            return self.error("Expected `)`");
+        }
+
+        Ok(expr)
+    }
+
+    fn operator(&mut self) -> Option<Operator> {
+        let operators = [
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+
+        for &(symbol, operator) in operators.iter() {
+            if self.eat_str(symbol) {
+                return Some(operator);
+            }
+        }
+
+        None
+    }
+
+    // Operand used on its own, which has to be a query testing for
+    // existence or a function returning a logical value
+    fn test(&self, operand: Comparable, offset: usize) -> JsonResult<Expr> {
+        match operand {
+            Comparable::Query(query) => Ok(Expr::Exists(query)),
+            Comparable::Call(function) => {
+                if !function.is_logical() {
+                    return self.error_at(offset, "Result of the function has to be compared");
+                }
+
+                Ok(Expr::Call(function))
+            },
+            Comparable::Literal(_) => self.error_at(offset, "Literal has to be compared"),
+        }
+    }
+
+    fn check_comparable(&self, operand: &Comparable, offset: usize) -> JsonResult<()> {
+        match *operand {
+            Comparable::Query(ref query) if !query.is_singular() => {
+                self.error_at(offset, "Only singular queries can be compared")
+            },
+            Comparable::Call(ref function) if function.is_logical() => {
+                self.error_at(offset, "Result of the function can't be compared")
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn comparable(&mut self) -> JsonResult<Comparable> {
+        match self.peek() {
+            Some(b'@') | Some(b'$') => {
+                let absolute = self.peek() == Some(b'$');
+
+                self.index += 1;
+
+                Ok(Comparable::Query(Query {
+                    absolute: absolute,
+                    segments: try!(self.segments()),
+                }))
+            },
+            Some(b'\'') | Some(b'"') => Ok(Comparable::Literal(try!(self.string()).into())),
+            Some(b'-') | Some(b'0' ... b'9') => Ok(Comparable::Literal(try!(self.number()))),
+            Some(b'a' ... b'z') => {
+                let source = self.source;
+                let start = self.index;
+
+                while let Some(b'a' ... b'z') | Some(b'0' ... b'9') | Some(b'_') = self.peek() {
+                    self.index += 1;
+                }
+
+                let name = &source[start .. self.index];
+
+                if self.peek() == Some(b'(') {
+                    return Ok(Comparable::Call(try!(self.function(name, start))));
+                }
+
+                Ok(Comparable::Literal(match name {
+                    "true"  => JsonValue::Boolean(true),
+                    "false" => JsonValue::Boolean(false),
+                    "null"  => JsonValue::Null,
+                    _       => return self.error_at(start, "Unknown literal"),
+                }))
+            },
+            _ => self.error("Expected a query, literal or function"),
+        }
+    }
+
+    fn number(&mut self) -> JsonResult<JsonValue> {
+        let start = self.index;
+
+        self.eat(b'-');
+
+        match self.peek() {
+            Some(b'0')          => self.index += 1,
+            Some(b'1' ... b'9') => { self.skip_digits(); },
+            _                   => return self.error("Expected a digit"),
+        }
+
+        if self.eat(b'.') && self.skip_digits() == 0 {
+            return self.error("Expected a digit");
+        }
+
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+
+            if self.skip_digits() == 0 {
+                return self.error("Expected a digit");
+            }
+        }
+
+        parse(&self.source[start .. self.index]).or_else(|_| self.error_at(start, "Invalid number"))
+    }
+
+    fn function(&mut self, name: &str, offset: usize) -> JsonResult<Function> {
+        try!(self.enter());
+
+        let function = self.call(name, offset);
+
+        self.depth -= 1;
+
+        function
+    }
+
+    fn call(&mut self, name: &str, offset: usize) -> JsonResult<Function> {
+        let mut arguments = Vec::new();
+
+        self.index += 1;
+        self.skip_whitespace();
+
+        if !self.eat(b')') {
+            loop {
+                self.skip_whitespace();
+                arguments.push((self.index, try!(self.argument())));
+                self.skip_whitespace();
+
+                if self.eat(b')') {
+                    break;
+                }
+
+                if !self.eat(b',') {
+                    return self.error("Expected `,` or `)`");
+                }
+            }
+        }
+
+        let expected = match name {
+            "length" | "count" | "value" => 1,
+            "match" | "search"           => 2,
+            _ => return self.error_at(offset, "Unknown function"),
+        };
+
+        if arguments.len() != expected {
+            return self.error_at(offset, "Wrong number of arguments");
+        }
+
+        let mut arguments = arguments.into_iter();
+
+        match name {
+            "length" => Ok(Function::Length(Box::new(try!(self.value_argument(arguments.next()))))),
+            "count"  => Ok(Function::Count(try!(self.nodes_argument(arguments.next())))),
+            "value"  => Ok(Function::Value(try!(self.nodes_argument(arguments.next())))),
+            _ => {
+                if !cfg!(feature = "regex") {
+                    return self.error_at(offset, "`match` and `search` need the `regex` feature");
+                }
+
+                let full = name == "match";
+                let value = try!(self.value_argument(arguments.next()));
+                let pattern = try!(self.value_argument(arguments.next()));
+
+                let compiled = match pattern {
+                    Comparable::Literal(JsonValue::String(ref pattern)) => regexp::compile(pattern, full),
+                    _ => None,
+                };
+
+                Ok(Function::Regex {
+                    full: full,
+                    value: Box::new(value),
+                    pattern: Box::new(pattern),
+                    compiled: compiled,
+                })
+            }
+        }
+    }
+
+    // Arguments that are a single literal, query or function call are kept
+    // as they are, anything else has to be a logical expression. Either way
+    // the argument is only parsed once, a logical expression starting with
+    // an operand continues from it.
+    fn argument(&mut self) -> JsonResult<Argument> {
+        match self.peek() {
+            Some(b'!') | Some(b'(') => {
+                try!(self.logical());
+
+                return Ok(Argument::Logical);
+            },
+            _ => {},
+        }
+
+        let start = self.index;
+        let operand = try!(self.comparable());
+        let end = self.index;
+
+        self.skip_whitespace();
+
+        if let Some(b',') | Some(b')') = self.peek() {
+            return Ok(Argument::Value(operand));
+        }
+
+        self.index = end;
+
+        try!(self.enter());
+
+        let expr = self.compare_or_test(operand, start)
+                       .and_then(|expr| self.and_rest(expr))
+                       .and_then(|expr| self.or_rest(expr));
+
+        self.depth -= 1;
+
+        try!(expr);
+
+        Ok(Argument::Logical)
+    }
+
+    fn value_argument(&self, argument: Option<(usize, Argument)>) -> JsonResult<Comparable> {
+        match argument {
+            Some((offset, Argument::Value(operand))) => {
+                try!(self.check_comparable(&operand, offset));
+                Ok(operand)
+            },
+            Some((offset, _)) => self.error_at(offset, "Expected a value"),
+            None              => unreachable!(),
+        }
+    }
+
+    fn nodes_argument(&self, argument: Option<(usize, Argument)>) -> JsonResult<Query> {
+        match argument {
+            Some((_, Argument::Value(Comparable::Query(query)))) => Ok(query),
+            Some((offset, _)) => self.error_at(offset, "Expected a query"),
+            None              => unreachable!(),
+        }
+    }
+}
+
+// State of a single run of a query
+struct Context<'a> {
+    root: &'a JsonValue,
+
+    // Patterns of `match` and `search` that aren't literals, compiled once
+    // for every distinct string. `None` if the string isn't an I-Regexp.
+    patterns: RefCell<HashMap<(String, bool), Option<regexp::Pattern>>>,
+}
+
+fn select<'a>(segments: &[Segment], context: &Context<'a>, start: Node<'a>) -> Vec<Node<'a>> {
+    let mut nodes = vec![start];
+
+    for segment in segments {
+        let mut selected = Vec::new();
+
+        for node in &nodes {
+            match *segment {
+                Segment::Child(ref selectors)      => apply(selectors, context, node, &mut selected),
+                Segment::Descendant(ref selectors) => descend(selectors, context, node, &mut selected),
+            }
+        }
+
+        nodes = selected;
+    }
+
+    nodes
+}
+
+// Applies the selectors to the node, and then to each of its descendants
+fn descend<'a>(selectors: &[Selector], context: &Context<'a>, node: &Node<'a>, selected: &mut Vec<Node<'a>>) {
+    apply(selectors, context, node, selected);
+
+    for child in children(node) {
+        descend(selectors, context, &child, selected);
+    }
+}
+
+fn children<'a>(node: &Node<'a>) -> Vec<Node<'a>> {
+    match *node.value {
+        JsonValue::Array(ref vec) => {
+            vec.iter().enumerate().map(|(index, value)| node.index(index, value)).collect()
+        },
+        JsonValue::Object(ref object) => {
+            object.iter().map(|(key, value)| node.key(key, value)).collect()
+        },
+        _ => Vec::new()
+    }
+}
+
+fn apply<'a>(selectors: &[Selector], context: &Context<'a>, node: &Node<'a>, selected: &mut Vec<Node<'a>>) {
+    for selector in selectors {
+        match *selector {
+            Selector::Name(ref name) => {
+                if let JsonValue::Object(ref object) = *node.value {
+                    if let Some(value) = object.get(name) {
+                        selected.push(node.key(name, value));
+                    }
+                }
+            },
+            Selector::Wildcard => selected.extend(children(node)),
+            Selector::Index(index) => {
+                if let JsonValue::Array(ref vec) = *node.value {
+                    let index = if index < 0 { index + vec.len() as i64 } else { index };
+
+                    if index >= 0 && index < vec.len() as i64 {
+                        selected.push(node.index(index as usize, &vec[index as usize]));
+                    }
+                }
+            },
+            Selector::Slice(start, end, step) => {
+                if let JsonValue::Array(ref vec) = *node.value {
+                    for index in slice(vec.len() as i64, start, end, step.unwrap_or(1)) {
+                        selected.push(node.index(index, &vec[index]));
+                    }
+                }
+            },
+            Selector::Filter(ref expr) => {
+                for child in children(node) {
+                    if test(expr, context, child.value) {
+                        selected.push(child);
+                    }
+                }
+            },
+        }
+    }
+}
+
+// Indexes selected by a slice, following section 2.3.4.2.2 of RFC 9535
+fn slice(len: i64, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let normalize = |index: i64| if index >= 0 { index } else { len + index };
+    let mut indexes = Vec::new();
+
+    if step > 0 {
+        let lower = normalize(start.unwrap_or(0)).max(0).min(len);
+        let upper = normalize(end.unwrap_or(len)).max(0).min(len);
+        let mut index = lower;
+
+        while index < upper {
+            indexes.push(index as usize);
+            index += step;
+        }
+    } else if step < 0 {
+        let upper = start.map_or(len - 1, normalize).max(-1).min(len - 1);
+        let lower = end.map_or(-1, normalize).max(-1).min(len - 1);
+        let mut index = upper;
+
+        while lower < index {
+            indexes.push(index as usize);
+            index += step;
+        }
+    }
+
+    indexes
+}
+
+fn run<'a>(query: &Query, context: &Context<'a>, current: &'a JsonValue) -> Vec<Node<'a>> {
+    let start = Node {
+        value: if query.absolute { context.root } else { current },
+        path: String::new(),
+    };
+
+    select(&query.segments, context, start)
+}
+
+fn test<'a>(expr: &'a Expr, context: &Context<'a>, current: &'a JsonValue) -> bool {
+    match *expr {
+        Expr::Or(ref exprs)  => exprs.iter().any(|expr| test(expr, context, current)),
+        Expr::And(ref exprs) => exprs.iter().all(|expr| test(expr, context, current)),
+        Expr::Not(ref expr)  => !test(expr, context, current),
+        Expr::Exists(ref query) => !run(query, context, current).is_empty(),
+        Expr::Call(ref function) => call_logical(function, context, current),
+        Expr::Compare(ref left, operator, ref right) => {
+            let left = evaluate(left, context, current);
+            let right = evaluate(right, context, current);
+
+            compare(left.as_deref(), operator, right.as_deref())
+        },
+    }
+}
+
+// Value of a comparable, `None` when it's nothing, ie: a query that didn't
+// select a node
+fn evaluate<'a>(operand: &'a Comparable, context: &Context<'a>, current: &'a JsonValue) -> Option<Cow<'a, JsonValue>> {
+    match *operand {
+        Comparable::Literal(ref value) => Some(Cow::Borrowed(value)),
+        Comparable::Query(ref query)   => single(run(query, context, current)),
+        Comparable::Call(ref function) => call_value(function, context, current),
+    }
+}
+
+fn single<'a>(mut nodes: Vec<Node<'a>>) -> Option<Cow<'a, JsonValue>> {
+    match nodes.pop() {
+        Some(node) if nodes.is_empty() => Some(Cow::Borrowed(node.value)),
+        _                              => None,
+    }
+}
+
+fn call_value<'a>(function: &'a Function, context: &Context<'a>, current: &'a JsonValue) -> Option<Cow<'a, JsonValue>> {
+    match *function {
+        Function::Length(ref operand) => {
+            let len = match evaluate(operand, context, current) {
+                Some(value) => match *value {
+                    JsonValue::String(ref string) => string.chars().count(),
+                    JsonValue::Array(ref vec)     => vec.len(),
+                    JsonValue::Object(ref object) => object.len(),
+                    _                             => return None,
+                },
+                None => return None,
+            };
+
+            Some(Cow::Owned(len.into()))
+        },
+        Function::Count(ref query) => Some(Cow::Owned(run(query, context, current).len().into())),
+        Function::Value(ref query) => single(run(query, context, current)),
+        Function::Regex { .. }     => None,
+    }
+}
+
+fn call_logical<'a>(function: &'a Function, context: &Context<'a>, current: &'a JsonValue) -> bool {
+    let (full, value, pattern, compiled) = match *function {
+        Function::Regex { full, ref value, ref pattern, ref compiled } => (full, value, pattern, compiled),
+        _ => return false,
+    };
+
+    let value = evaluate(value, context, current);
+    let value = match value.as_ref().and_then(|value| value.as_str()) {
+        Some(value) => value,
+        None        => return false,
+    };
+
+    if let Some(ref compiled) = *compiled {
+        return compiled.is_match(value);
+    }
+
+    let pattern = evaluate(pattern, context, current);
+    let pattern = match pattern.as_ref().and_then(|pattern| pattern.as_str()) {
+        Some(pattern) => pattern,
+        None          => return false,
+    };
+
+    let mut patterns = context.patterns.borrow_mut();
+    let compiled = patterns.entry((pattern.into(), full)).or_insert_with(|| regexp::compile(pattern, full));
+
+    match *compiled {
+        Some(ref compiled) => compiled.is_match(value),
+        None               => false,
+    }
+}
+
+fn compare(left: Option<&JsonValue>, operator: Operator, right: Option<&JsonValue>) -> bool {
+    match operator {
+        Operator::Equal          => left == right,
+        Operator::NotEqual       => left != right,
+        Operator::Less           => less(left, right),
+        Operator::LessOrEqual    => less(left, right) || left == right,
+        Operator::Greater        => less(right, left),
+        Operator::GreaterOrEqual => less(right, left) || left == right,
+    }
+}
+
+// Only numbers and strings can be ordered, strings by their code points
+fn less(left: Option<&JsonValue>, right: Option<&JsonValue>) -> bool {
+    let (left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        _                         => return false,
+    };
+
+    let order = match *left {
+        JsonValue::String(ref left) => match *right {
+            JsonValue::String(ref right) => left.partial_cmp(right),
+            _                            => None,
+        },
+        JsonValue::Integer(ref left) => match *right {
+            JsonValue::Integer(ref right) => left.partial_cmp(right),
+            JsonValue::Number(right)      => left.partial_cmp_f64(right),
+            _                             => None,
+        },
+        JsonValue::Number(left) => match *right {
+            JsonValue::Number(right)      => left.partial_cmp(&right),
+            JsonValue::Integer(ref right) => right.partial_cmp_f64(left).map(Ordering::reverse),
+            _                             => None,
+        },
+        _ => None,
+    };
+
+    order == Some(Ordering::Less)
+}
+
+// I-Regexp patterns (RFC 9485) used by `match` and `search`, run with the
+// `regex` crate.
+#[cfg(feature = "regex")]
+mod regexp {
+    extern crate regex;
+
+    pub use self::regex::Regex as Pattern;
+
+    // Categories of `\p{..}` and `\P{..}`, each with the letters that can
+    // follow it to name a subcategory
+    const CATEGORIES: &[(char, &str)] = &[
+        ('L', "lmotu"),
+        ('M', "cen"),
+        ('N', "dlo"),
+        ('P', "cdefios"),
+        ('Z', "lps"),
+        ('S', "ckmo"),
+        ('C', "cfno"),
+    ];
+
+    // Characters that can be escaped with a backslash
+    const SINGLE_CHAR_ESCAPES: &str = "()*+-.?[\\]^nrt{|}";
+
+    // Compiles the pattern, or returns `None` if it isn't an I-Regexp. The
+    // `regex` crate has a superset of its syntax, so the pattern is checked
+    // while it's rewritten: anchors, flags, lazy quantifiers and shorthand
+    // classes such as `\d` are rejected. In I-Regexp `.` doesn't match `\r`
+    // either, and `&` and `~` in classes are characters, not operators.
+    pub fn compile(pattern: &str, full: bool) -> Option<Pattern> {
+        let mut translator = Translator {
+            chars: pattern.chars().collect(),
+            index: 0,
+            output: String::with_capacity(pattern.len() + 16),
+        };
+
+        if full {
+            translator.output.push_str("^(?:");
+        }
+
+        if !translator.translate() {
+            return None;
+        }
+
+        if full {
+            translator.output.push_str(")$");
+        }
+
+        Pattern::new(&translator.output).ok()
+    }
+
+    struct Translator {
+        chars: Vec<char>,
+        index: usize,
+        output: String,
+    }
+
+    impl Translator {
+        fn next(&mut self) -> Option<char> {
+            let ch = self.chars.get(self.index).cloned();
+            self.index += 1;
+            ch
+        }
+
+        fn peek(&self) -> Option<char> {
+            self.chars.get(self.index).cloned()
+        }
+
+        fn translate(&mut self) -> bool {
+            let mut groups = 0;
+
+            // Whether the last thing written can take a quantifier
+            let mut atom = false;
+
+            while let Some(ch) = self.next() {
+                atom = match ch {
+                    '(' => {
+                        groups += 1;
+                        self.output.push(ch);
+                        false
+                    },
+                    ')' => {
+                        if groups == 0 {
+                            return false;
+                        }
+
+                        groups -= 1;
+                        self.output.push(ch);
+                        true
+                    },
+                    '|' => {
+                        self.output.push(ch);
+                        false
+                    },
+                    '*' | '+' | '?' if atom => {
+                        self.output.push(ch);
+                        false
+                    },
+                    '{' if atom => {
+                        if !self.range_quantifier() {
+                            return false;
+                        }
+
+                        false
+                    },
+                    '.' => {
+                        self.output.push_str("[^\\n\\r]");
+                        true
+                    },
+                    '[' => {
+                        if !self.class() {
+                            return false;
+                        }
+
+                        true
+                    },
+                    '\\' => {
+                        if self.escape().is_none() {
+                            return false;
+                        }
+
+                        true
+                    },
+                    // Quantifiers without an atom before them, and anchors
+                    '*' | '+' | '?' | '{' | '}' | ']' | '^' | '$' => return false,
+                    _ => {
+                        self.output.push(ch);
+                        true
+                    }
+                };
+            }
+
+            groups == 0
+        }
+
+        // `{n}`, `{n,}` or `{n,m}`, the opening brace is already read
+        fn range_quantifier(&mut self) -> bool {
+            self.output.push('{');
+
+            if !self.digits() {
+                return false;
+            }
+
+            if self.peek() == Some(',') {
+                self.index += 1;
+                self.output.push(',');
+                self.digits();
+            }
+
+            match self.next() {
+                Some('}') => {
+                    self.output.push('}');
+                    true
+                },
+                _ => false,
+            }
+        }
+
+        fn digits(&mut self) -> bool {
+            let start = self.index;
+
+            while let Some(ch) = self.peek() {
+                if !ch.is_ascii_digit() {
+                    break;
+                }
+
+                self.index += 1;
+                self.output.push(ch);
+            }
+
+            self.index > start
+        }
+
+        // Escape sequence after a backslash. Returns whether it stands for a
+        // single character, `None` if it isn't valid.
+        fn escape(&mut self) -> Option<bool> {
+            match self.next() {
+                Some(ch) if SINGLE_CHAR_ESCAPES.contains(ch) => {
+                    self.output.push('\\');
+                    self.output.push(ch);
+
+                    Some(true)
+                },
+                Some(ch) if ch == 'p' || ch == 'P' => {
+                    self.output.push('\\');
+                    self.output.push(ch);
+
+                    if self.category() { Some(false) } else { None }
+                },
+                _ => None,
+            }
+        }
+
+        // Name of a category in braces, after `\p` or `\P`
+        fn category(&mut self) -> bool {
+            if self.next() != Some('{') {
+                return false;
+            }
+
+            let category = match self.next() {
+                Some(category) => category,
+                None           => return false,
+            };
+
+            let subcategories = match CATEGORIES.iter().find(|&&(name, _)| name == category) {
+                Some(&(_, subcategories)) => subcategories,
+                None                      => return false,
+            };
+
+            self.output.push('{');
+            self.output.push(category);
+
+            match self.next() {
+                Some('}') => {},
+                Some(sub) if subcategories.contains(sub) && self.next() == Some('}') => self.output.push(sub),
+                _ => return false,
+            }
+
+            self.output.push('}');
+
+            true
+        }
+
+        // Character class, the opening bracket is already read. A `-` is
+        // only a character at the start or the end of the class.
+        fn class(&mut self) -> bool {
+            self.output.push('[');
+
+            if self.peek() == Some('^') {
+                self.index += 1;
+                self.output.push('^');
+            }
+
+            let mut empty = true;
+
+            if self.peek() == Some('-') {
+                self.index += 1;
+                self.output.push_str("\\-");
+                empty = false;
+            }
+
+            loop {
+                match self.peek() {
+                    Some(']') if !empty => {
+                        self.index += 1;
+                        self.output.push(']');
+
+                        return true;
+                    },
+                    Some('-') => {
+                        self.index += 1;
+
+                        if self.peek() != Some(']') {
+                            return false;
+                        }
+
+                        self.output.push_str("\\-");
+                    },
+                    _ => {
+                        let single = match self.class_char() {
+                            Some(single) => single,
+                            None         => return false,
+                        };
+
+                        // A range, unless the `-` ends the class
+                        if single && self.peek() == Some('-') && self.chars.get(self.index + 1) != Some(&']') {
+                            self.index += 1;
+                            self.output.push('-');
+
+                            if self.class_char() != Some(true) {
+                                return false;
+                            }
+                        }
+                    },
+                }
+
+                empty = false;
+            }
+        }
+
+        // Character in a class, or an escape sequence. Returns whether it's a
+        // single character, which can be the end of a range.
+        fn class_char(&mut self) -> Option<bool> {
+            match self.next() {
+                None | Some('-') | Some('[') | Some(']') => None,
+                Some('\\') => self.escape(),
+                Some(ch) => {
+                    // Operators in classes of the `regex` crate
+                    if ch == '&' || ch == '~' || ch == '^' {
+                        self.output.push('\\');
+                    }
+
+                    self.output.push(ch);
+
+                    Some(true)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "regex"))]
+mod regexp {
+    // Never constructed, queries using regular expressions don't compile
+    // without the `regex` feature
+    #[derive(Debug, Clone)]
+    pub enum Pattern {}
+
+    impl Pattern {
+        pub fn is_match(&self, _: &str) -> bool {
+            match *self {}
+        }
+    }
+
+    pub fn compile(_: &str, _: bool) -> Option<Pattern> {
+        None
+    }
+}
@@ -0,0 +1,240 @@
+// Word-at-a-time search for bytes that can't be taken verbatim inside of
+// a JSON string: double quotes, backslashes and control characters. Both
+// the parser and the generators use it to skip over the plain runs in
+// strings, falling back to a byte-by-byte loop only when they need to.
+//
+// On x86_64 the scan uses SSE2, or AVX2 if the CPU supports it. Elsewhere
+// it checks 8 bytes at a time using arithmetic on `u64` words (SWAR).
+
+use std::ptr;
+
+const ONES: u64 = 0x0101010101010101;
+const HIGH: u64 = 0x8080808080808080;
+
+const QUOTES: u64 = ONES * b'"' as u64;
+const BACKSLASHES: u64 = ONES * b'\\' as u64;
+const CONTROL: u64 = ONES * 0x20;
+
+// Returns the index of the first double quote, backslash or control
+// character in `bytes`, or the length of `bytes` if there isn't one.
+#[inline]
+pub fn find_special(bytes: &[u8]) -> usize {
+    let mut index = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if bytes.len() >= 16 {
+            index = x86::find_special(bytes);
+
+            if index < bytes.len() {
+                return index;
+            }
+
+            // The vector loop handles whole blocks only, the remaining
+            // bytes are checked below.
+            index = bytes.len() - bytes.len() % 16;
+        }
+    }
+
+    find_special_words(bytes, index)
+}
+
+// Checks 8 bytes at a time from `index` on, then the remaining bytes one
+// at a time.
+#[inline(always)]
+fn find_special_words(bytes: &[u8], mut index: usize) -> usize {
+    while index + 8 <= bytes.len() {
+        let word = unsafe { read_word(bytes, index) };
+        let mask = special_mask(word);
+
+        if mask != 0 {
+            return index + (mask.trailing_zeros() / 8) as usize;
+        }
+
+        index += 8;
+    }
+
+    while index < bytes.len() {
+        if is_special(bytes[index]) {
+            return index;
+        }
+
+        index += 1;
+    }
+
+    index
+}
+
+#[inline(always)]
+fn is_special(ch: u8) -> bool {
+    ch < 0x20 || ch == b'"' || ch == b'\\'
+}
+
+// Reads 8 bytes so that the byte at `index` is the least significant one
+// regardless of the platform endianness. Caller must make sure there are
+// at least 8 bytes available from `index`.
+#[inline(always)]
+unsafe fn read_word(bytes: &[u8], index: usize) -> u64 {
+    u64::from_le(ptr::read_unaligned(bytes.as_ptr().add(index) as *const u64))
+}
+
+// Sets the high bit of every byte in the word that is special. Borrows
+// only ever propagate towards the more significant bytes, so the lowest
+// set bit always marks the first special byte, even though the bytes
+// after it might be marked incorrectly.
+#[inline(always)]
+fn special_mask(word: u64) -> u64 {
+    let quotes = word ^ QUOTES;
+    let backslashes = word ^ BACKSLASHES;
+
+    let zero_quotes = quotes.wrapping_sub(ONES) & !quotes;
+    let zero_backslashes = backslashes.wrapping_sub(ONES) & !backslashes;
+    let control = word.wrapping_sub(CONTROL) & !word;
+
+    (zero_quotes | zero_backslashes | control) & HIGH
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    // Checks all whole blocks of 16 bytes, returns the length of `bytes`
+    // if none of the blocks contain a special byte.
+    #[inline]
+    pub fn find_special(bytes: &[u8]) -> usize {
+        if bytes.len() >= 32 && is_x86_feature_detected!("avx2") {
+            return unsafe { find_special_avx2(bytes) };
+        }
+
+        // SSE2 is always available on x86_64
+        unsafe { find_special_sse2(bytes) }
+    }
+
+    #[inline]
+    pub(super) unsafe fn find_special_sse2(bytes: &[u8]) -> usize {
+        let quote = _mm_set1_epi8(b'"' as i8);
+        let backslash = _mm_set1_epi8(b'\\' as i8);
+        let control = _mm_set1_epi8(0x1F);
+
+        let mut index = 0;
+
+        while index + 16 <= bytes.len() {
+            let block = _mm_loadu_si128(bytes.as_ptr().add(index) as *const __m128i);
+
+            // There is no unsigned comparison in SSE2, but a byte is at most
+            // 0x1F if taking a minimum with 0x1F doesn't change it.
+            let is_control = _mm_cmpeq_epi8(_mm_min_epu8(block, control), block);
+            let is_quote = _mm_cmpeq_epi8(block, quote);
+            let is_backslash = _mm_cmpeq_epi8(block, backslash);
+
+            let special = _mm_or_si128(is_control, _mm_or_si128(is_quote, is_backslash));
+            let mask = _mm_movemask_epi8(special);
+
+            if mask != 0 {
+                return index + mask.trailing_zeros() as usize;
+            }
+
+            index += 16;
+        }
+
+        bytes.len()
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn find_special_avx2(bytes: &[u8]) -> usize {
+        let quote = _mm256_set1_epi8(b'"' as i8);
+        let backslash = _mm256_set1_epi8(b'\\' as i8);
+        let control = _mm256_set1_epi8(0x1F);
+
+        let mut index = 0;
+
+        while index + 32 <= bytes.len() {
+            let block = _mm256_loadu_si256(bytes.as_ptr().add(index) as *const __m256i);
+
+            let is_control = _mm256_cmpeq_epi8(_mm256_min_epu8(block, control), block);
+            let is_quote = _mm256_cmpeq_epi8(block, quote);
+            let is_backslash = _mm256_cmpeq_epi8(block, backslash);
+
+            let special = _mm256_or_si256(is_control, _mm256_or_si256(is_quote, is_backslash));
+            let mask = _mm256_movemask_epi8(special);
+
+            if mask != 0 {
+                return index + mask.trailing_zeros() as usize;
+            }
+
+            index += 32;
+        }
+
+        // Pass the leftover 16 byte block, if there is one, to SSE2
+        if bytes.len() - index >= 16 {
+            return index + find_special_sse2(&bytes[index ..]);
+        }
+
+        bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::ALLOWED;
+
+    // Plain byte-by-byte loop the vectorized scans are checked against
+    fn find_special_scalar(bytes: &[u8]) -> usize {
+        bytes.iter().position(|&ch| !ALLOWED[ch as usize]).unwrap_or(bytes.len())
+    }
+
+    // Calls `check` with every byte at every position of buffers of every
+    // length from 33 to 64, which covers every remainder of the 8, 16 and
+    // 32 byte blocks. Each length starts at a different offset within 32
+    // bytes of an allocation, so every alignment is covered as well. The
+    // rest of the buffer cycles through the bytes that aren't special.
+    fn for_each_buffer<F: FnMut(&[u8])>(mut check: F) {
+        let plain: Vec<u8> = (0 .. 256).map(|ch| ch as u8).filter(|&ch| ALLOWED[ch as usize]).collect();
+
+        for len in 33 .. 65 {
+            let offset = len - 33;
+            let mut storage: Vec<u8> = (0 .. offset + len).map(|index| plain[index % plain.len()]).collect();
+
+            for position in offset .. offset + len {
+                let original = storage[position];
+
+                for ch in 0 .. 256 {
+                    storage[position] = ch as u8;
+                    check(&storage[offset ..]);
+                }
+
+                storage[position] = original;
+            }
+        }
+    }
+
+    #[test]
+    fn find_special_matches_scalar() {
+        for_each_buffer(|bytes| {
+            let expected = find_special_scalar(bytes);
+
+            assert_eq!(super::find_special(bytes), expected, "{:?}", bytes);
+            assert_eq!(super::find_special_words(bytes, 0), expected, "{:?}", bytes);
+        });
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn find_special_x86_matches_scalar() {
+        let avx2 = is_x86_feature_detected!("avx2");
+
+        for_each_buffer(|bytes| {
+            // The vector scans only look at whole blocks of 16 bytes
+            let expected = match find_special_scalar(bytes) {
+                index if index < bytes.len() - bytes.len() % 16 => index,
+                _                                               => bytes.len(),
+            };
+
+            assert_eq!(unsafe { super::x86::find_special_sse2(bytes) }, expected, "{:?}", bytes);
+
+            if avx2 {
+                assert_eq!(unsafe { super::x86::find_special_avx2(bytes) }, expected, "{:?}", bytes);
+            }
+        });
+    }
+}
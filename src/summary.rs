@@ -0,0 +1,171 @@
+use std::io;
+use codegen::Generator;
+use { JsonValue, PrettyOptions };
+
+/// Limits for `JsonValue::summarize`, which makes the output of huge values
+/// small enough to be logged. Parts of the value that are over the limits
+/// are replaced with markers, the output stays valid JSON:
+///
+/// - arrays get a last item such as `"…(1234 more items)"`,
+/// - objects get a last entry such as `"…": "(12 more entries)"`,
+/// - strings end with `…(300 more bytes)`,
+/// - arrays and objects nested too deep only have the marker in them.
+///
+/// ```
+/// # #[macro_use] extern crate json;
+/// # fn main() {
+/// # if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) { return; }
+/// use json::SummaryOptions;
+///
+/// let data = object!{
+///     "ids" => (0..1000).collect::<Vec<i32>>(),
+///     "name" => "a very long name"
+/// };
+///
+/// let options = SummaryOptions::new().max_items(3).max_string_len(6);
+///
+/// assert_eq!(data.summarize(options), r#"{"ids":[0,1,2,"…(997 more items)"],"name":"a very…(10 more bytes)"}"#);
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryOptions {
+    max_depth: usize,
+    max_string_len: usize,
+    max_items: usize,
+    max_bytes: usize,
+    pretty: Option<PrettyOptions>,
+}
+
+impl SummaryOptions {
+    /// Show 8 levels of nesting, 256 bytes of every string, 32 items of
+    /// every array or object and stop at around 4096 bytes of output, all
+    /// on a single line.
+    pub fn new() -> Self {
+        SummaryOptions {
+            max_depth: 8,
+            max_string_len: 256,
+            max_items: 32,
+            max_bytes: 4096,
+            pretty: None,
+        }
+    }
+
+    /// Number of nested arrays and objects whose contents are shown, `0`
+    /// only shows markers in the outermost one.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Number of bytes shown of every string, including keys. Strings are
+    /// only ever cut between characters, so fewer bytes may be shown.
+    pub fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = len;
+        self
+    }
+
+    /// Number of items of arrays and entries of objects shown.
+    pub fn max_items(mut self, items: usize) -> Self {
+        self.max_items = items;
+        self
+    }
+
+    /// Once the output is this long the rest of the items and entries are
+    /// replaced with markers. Output ends up longer than this by the length
+    /// of the markers and the last value written.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Pretty print the summary. Takes either a number of spaces to indent
+    /// new blocks with, or `PrettyOptions`. Summaries can't fail, so NaN and
+    /// infinite numbers are written as `null` regardless of the options.
+    pub fn pretty<O>(mut self, options: O) -> Self where O: Into<PrettyOptions> {
+        self.pretty = Some(options.into());
+        self
+    }
+
+    pub(crate) fn pretty_options(&self) -> Option<PrettyOptions> {
+        self.pretty
+    }
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        SummaryOptions::new()
+    }
+}
+
+// Writes the summary of `json` using the hooks of the generator, which only
+// ever does the formatting.
+pub fn write_summary<G>(gen: &mut G, json: &JsonValue, options: &SummaryOptions, depth: usize) -> io::Result<()>
+where G: Generator<T = Vec<u8>> {
+    match *json {
+        JsonValue::String(ref string) => write_string(gen, string, options),
+        JsonValue::Array(ref array) => {
+            let shown = if depth < options.max_depth { options.max_items } else { 0 };
+
+            try!(gen.begin_array());
+
+            for (index, item) in array.iter().enumerate() {
+                try!(gen.begin_array_value(index == 0));
+
+                if index >= shown || gen.get_writer().len() >= options.max_bytes {
+                    try!(gen.write_string(&format!("…({} more items)", array.len() - index)));
+                    break;
+                }
+
+                try!(write_summary(gen, item, options, depth + 1));
+            }
+
+            gen.end_array(array.is_empty())
+        },
+        JsonValue::Object(ref object) => {
+            let shown = if depth < options.max_depth { options.max_items } else { 0 };
+
+            try!(gen.begin_object());
+
+            for (index, (key, value)) in object.iter().enumerate() {
+                try!(gen.begin_object_key(index == 0));
+
+                if index >= shown || gen.get_writer().len() >= options.max_bytes {
+                    try!(gen.write_key("…"));
+                    try!(gen.begin_object_value());
+                    try!(gen.write_string(&format!("({} more entries)", object.len() - index)));
+                    break;
+                }
+
+                if key.len() > options.max_string_len {
+                    try!(gen.write_key(&truncate(key, options.max_string_len)));
+                } else {
+                    try!(gen.write_key(key));
+                }
+
+                try!(gen.begin_object_value());
+                try!(write_summary(gen, value, options, depth + 1));
+            }
+
+            gen.end_object(object.is_empty())
+        },
+        _ => gen.write_json(json)
+    }
+}
+
+fn write_string<G: Generator>(gen: &mut G, string: &str, options: &SummaryOptions) -> io::Result<()> {
+    if string.len() > options.max_string_len {
+        gen.write_string(&truncate(string, options.max_string_len))
+    } else {
+        gen.write_string(string)
+    }
+}
+
+fn truncate(string: &str, len: usize) -> String {
+    let mut end = len;
+
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}…({} more bytes)", &string[.. end], string.len() - end)
+}
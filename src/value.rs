@@ -1,8 +1,9 @@
-use std::collections::BTreeMap;
 use std::ops::{ Index, IndexMut, Deref };
 use iterators::{ Members, MembersMut, Entries, EntriesMut };
-use { JsonResult, JsonError };
-use std::{ mem, usize, u8, u16, u32, u64, isize, i8, i16, i32, i64, f32 };
+use { JsonResult, JsonError, Object, Integer, DumpOptions, NonFinite };
+use pointer;
+use query::{ JsonPath, Node };
+use std::{ mem, usize, u8, u16, u32, u64, u128, isize, i8, i16, i32, i64, i128, f32 };
 
 macro_rules! f64_to_unsinged {
     ($unsigned:ident, $value:expr) => {
@@ -24,13 +25,38 @@ macro_rules! f64_to_singed {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+macro_rules! integer_to_unsigned {
+    ($unsigned:ident, $value:expr) => {
+        match $value.as_u64() {
+            Some(value) if value <= $unsigned::MAX as u64 => Some(value as $unsigned),
+            _ => None
+        }
+    }
+}
+
+macro_rules! integer_to_signed {
+    ($signed:ident, $value:expr) => {
+        match $value.as_i64() {
+            Some(value) if value >= $signed::MIN as i64 && value <= $signed::MAX as i64 => {
+                Some(value as $signed)
+            },
+            _ => None
+        }
+    }
+}
+
+/// Numbers without a fraction or an exponent are parsed into `Integer`, as
+/// are all Rust integers converted into `JsonValue`. Everything else is a
+/// `Number`. Numbers compare equal when their values are exactly the same,
+/// regardless of the variant, so `1` equals `1.0`.
+#[derive(Debug, Clone)]
 pub enum JsonValue {
     String(String),
     Number(f64),
+    Integer(Integer),
     Boolean(bool),
     Null,
-    Object(BTreeMap<String, JsonValue>),
+    Object(Object),
     Array(Vec<JsonValue>),
 }
 
@@ -40,7 +66,7 @@ impl JsonValue {
     /// Create an empty `JsonValue::Object` instance.
     /// When creating an object with data, consider using the `object!` macro.
     pub fn new_object() -> JsonValue {
-        JsonValue::Object(BTreeMap::new())
+        JsonValue::Object(Object::new())
     }
 
     /// Create an empty `JsonValue::Array` instance.
@@ -56,10 +82,19 @@ impl JsonValue {
         }
     }
 
+    /// Checks whether the value is a number, either `Number` or `Integer`.
     pub fn is_number(&self) -> bool {
         match *self {
-            JsonValue::Number(_) => true,
-            _                    => false,
+            JsonValue::Number(_)  => true,
+            JsonValue::Integer(_) => true,
+            _                     => false,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            JsonValue::Integer(_) => true,
+            _                     => false,
         }
     }
 
@@ -103,6 +138,7 @@ impl JsonValue {
         match *self {
             JsonValue::String(ref value)  => value.is_empty(),
             JsonValue::Number(ref value)  => !value.is_normal(),
+            JsonValue::Integer(ref value) => value.as_i64() == Some(0),
             JsonValue::Boolean(ref value) => !value,
             JsonValue::Null               => true,
             JsonValue::Array(ref value)   => value.is_empty(),
@@ -117,10 +153,12 @@ impl JsonValue {
         }
     }
 
+    /// Converts integers to the nearest `f64`, see `Integer::as_f64`.
     pub fn as_f64(&self) -> Option<f64> {
         match *self {
-            JsonValue::Number(ref value) => Some(*value),
-            _                            => None
+            JsonValue::Number(ref value)  => Some(*value),
+            JsonValue::Integer(ref value) => Some(value.as_f64()),
+            _                             => None
         }
     }
 
@@ -128,44 +166,187 @@ impl JsonValue {
         self.as_f64().and_then(|value| f64_to_singed!(f32, value))
     }
 
+    pub fn as_u128(&self) -> Option<u128> {
+        match *self {
+            JsonValue::Integer(ref value) => value.as_u128(),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(u128, value))
+        }
+    }
+
     pub fn as_u64(&self) -> Option<u64> {
-        self.as_f64().and_then(|value| f64_to_unsinged!(u64, value))
+        match *self {
+            JsonValue::Integer(ref value) => value.as_u64(),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(u64, value))
+        }
     }
 
     pub fn as_u32(&self) -> Option<u32> {
-        self.as_f64().and_then(|value| f64_to_unsinged!(u32, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_unsigned!(u32, value),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(u32, value))
+        }
     }
 
     pub fn as_u16(&self) -> Option<u16> {
-        self.as_f64().and_then(|value| f64_to_unsinged!(u16, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_unsigned!(u16, value),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(u16, value))
+        }
     }
 
     pub fn as_u8(&self) -> Option<u8> {
-        self.as_f64().and_then(|value| f64_to_unsinged!(u8, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_unsigned!(u8, value),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(u8, value))
+        }
     }
 
     pub fn as_usize(&self) -> Option<usize> {
-        self.as_f64().and_then(|value| f64_to_unsinged!(usize, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_unsigned!(usize, value),
+            _ => self.as_f64().and_then(|value| f64_to_unsinged!(usize, value))
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        match *self {
+            JsonValue::Integer(ref value) => value.as_i128(),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(i128, value))
+        }
     }
 
     pub fn as_i64(&self) -> Option<i64> {
-        self.as_f64().and_then(|value| f64_to_singed!(i64, value))
+        match *self {
+            JsonValue::Integer(ref value) => value.as_i64(),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(i64, value))
+        }
     }
 
     pub fn as_i32(&self) -> Option<i32> {
-        self.as_f64().and_then(|value| f64_to_singed!(i32, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_signed!(i32, value),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(i32, value))
+        }
     }
 
     pub fn as_i16(&self) -> Option<i16> {
-        self.as_f64().and_then(|value| f64_to_singed!(i16, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_signed!(i16, value),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(i16, value))
+        }
     }
 
     pub fn as_i8(&self) -> Option<i8> {
-        self.as_f64().and_then(|value| f64_to_singed!(i8, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_signed!(i8, value),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(i8, value))
+        }
     }
 
     pub fn as_isize(&self) -> Option<isize> {
-        self.as_f64().and_then(|value| f64_to_singed!(isize, value))
+        match *self {
+            JsonValue::Integer(ref value) => integer_to_signed!(isize, value),
+            _ => self.as_f64().and_then(|value| f64_to_singed!(isize, value))
+        }
+    }
+
+    /// Strict versions of `as_u128`, `as_i64` and the rest, which only
+    /// accept numbers that are integers within range of the type. Fails with
+    /// `JsonError::FractionalNumber` or `JsonError::NumberOutOfRange` instead
+    /// of truncating or rounding, and with `JsonError::WrongType` when the
+    /// value isn't a number.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = array![42, 2.0, 1.9, 300, -1];
+    ///
+    /// assert_eq!(data[0].as_u8_exact(), Ok(42));
+    /// assert_eq!(data[1].as_u8_exact(), Ok(2));
+    /// assert_eq!(data[2].as_u8_exact().unwrap_err().to_string(), "Number 1.9 is not an integer, expected: u8");
+    /// assert_eq!(data[3].as_u8_exact().unwrap_err().to_string(), "Number 300 is out of range of u8");
+    /// assert_eq!(data[4].as_u64_exact().unwrap_err().code(), "number_out_of_range");
+    /// # }
+    /// ```
+    pub fn as_u128_exact(&self) -> JsonResult<u128> {
+        self.as_exact("u128", |value| value.as_u128())
+    }
+
+    pub fn as_u64_exact(&self) -> JsonResult<u64> {
+        self.as_exact("u64", |value| value.as_u64())
+    }
+
+    pub fn as_u32_exact(&self) -> JsonResult<u32> {
+        self.as_exact("u32", |value| integer_to_unsigned!(u32, value))
+    }
+
+    pub fn as_u16_exact(&self) -> JsonResult<u16> {
+        self.as_exact("u16", |value| integer_to_unsigned!(u16, value))
+    }
+
+    pub fn as_u8_exact(&self) -> JsonResult<u8> {
+        self.as_exact("u8", |value| integer_to_unsigned!(u8, value))
+    }
+
+    pub fn as_usize_exact(&self) -> JsonResult<usize> {
+        self.as_exact("usize", |value| integer_to_unsigned!(usize, value))
+    }
+
+    pub fn as_i128_exact(&self) -> JsonResult<i128> {
+        self.as_exact("i128", |value| value.as_i128())
+    }
+
+    pub fn as_i64_exact(&self) -> JsonResult<i64> {
+        self.as_exact("i64", |value| value.as_i64())
+    }
+
+    pub fn as_i32_exact(&self) -> JsonResult<i32> {
+        self.as_exact("i32", |value| integer_to_signed!(i32, value))
+    }
+
+    pub fn as_i16_exact(&self) -> JsonResult<i16> {
+        self.as_exact("i16", |value| integer_to_signed!(i16, value))
+    }
+
+    pub fn as_i8_exact(&self) -> JsonResult<i8> {
+        self.as_exact("i8", |value| integer_to_signed!(i8, value))
+    }
+
+    pub fn as_isize_exact(&self) -> JsonResult<isize> {
+        self.as_exact("isize", |value| integer_to_signed!(isize, value))
+    }
+
+    // Reads the number as an `Integer` and converts it with `convert`,
+    // failing instead of losing any part of it.
+    fn as_exact<T, F>(&self, target: &'static str, convert: F) -> JsonResult<T>
+    where F: FnOnce(&Integer) -> Option<T> {
+        let integer = match *self {
+            JsonValue::Integer(value) => Some(value),
+            JsonValue::Number(value) => {
+                if value.is_finite() && value.fract() != 0.0 {
+                    return Err(JsonError::FractionalNumber {
+                        number: self.dump_number(),
+                        target: target,
+                    });
+                }
+
+                Integer::from_f64(value)
+            },
+            _ => return Err(JsonError::wrong_type("Number"))
+        };
+
+        match integer.as_ref().and_then(convert) {
+            Some(value) => Ok(value),
+            None => Err(JsonError::NumberOutOfRange {
+                number: self.dump_number(),
+                target: target,
+            })
+        }
+    }
+
+    // Number as written in JSON, with NaN and infinity spelled out
+    fn dump_number(&self) -> String {
+        self.dump_with(DumpOptions::new().non_finite(NonFinite::Literal)).unwrap_or_default()
     }
 
     pub fn as_bool(&self) -> Option<bool> {
@@ -273,8 +454,8 @@ impl JsonValue {
             JsonValue::Array(ref vec) => {
                 vec.len()
             },
-            JsonValue::Object(ref btree) => {
-                btree.len()
+            JsonValue::Object(ref object) => {
+                object.len()
             },
             _ => 0
         }
@@ -303,8 +484,8 @@ impl JsonValue {
     /// Works on `JsonValue::Object` - returns an iterator over key value pairs.
     pub fn entries(&self) -> Entries {
         match *self {
-            JsonValue::Object(ref btree) => {
-                Entries::Some(btree.iter())
+            JsonValue::Object(ref object) => {
+                Entries::Some(object.iter())
             },
             _ => Entries::None
         }
@@ -314,8 +495,8 @@ impl JsonValue {
     /// key value pairs.
     pub fn entries_mut(&mut self) -> EntriesMut {
         match *self {
-            JsonValue::Object(ref mut btree) => {
-                EntriesMut::Some(btree.iter_mut())
+            JsonValue::Object(ref mut object) => {
+                EntriesMut::Some(object.iter_mut())
             },
             _ => EntriesMut::None
         }
@@ -326,8 +507,8 @@ impl JsonValue {
     /// object, it will return a null.
     pub fn remove(&mut self, key: &str) -> JsonValue {
         match *self {
-            JsonValue::Object(ref mut btree) => {
-                btree.remove(key).unwrap_or(JsonValue::Null)
+            JsonValue::Object(ref mut object) => {
+                object.remove(key).unwrap_or(JsonValue::Null)
             },
             _ => JsonValue::Null
         }
@@ -338,11 +519,124 @@ impl JsonValue {
     pub fn clear(&mut self) {
         match *self {
             JsonValue::String(ref mut string) => string.clear(),
-            JsonValue::Object(ref mut btree)  => btree.clear(),
+            JsonValue::Object(ref mut object) => object.clear(),
             JsonValue::Array(ref mut vec)     => vec.clear(),
             _                                 => *self = JsonValue::Null,
         }
     }
+
+    /// Looks up a nested value by its JSON Pointer, as defined in RFC 6901.
+    /// The empty pointer refers to the value itself, `~1` and `~0` in a
+    /// pointer stand for `/` and `~` in a key.
+    ///
+    /// Fails with `JsonError::InvalidPointer` if the pointer is malformed,
+    /// and with `JsonError::PointerNotFound` holding the segment that
+    /// couldn't be followed if there is no value at it.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = object!{
+    ///     "users" => array![
+    ///         object!{ "name" => "Alice", "a/b" => 1 }
+    ///     ]
+    /// };
+    ///
+    /// assert!(*data.pointer("/users/0/name").unwrap() == "Alice");
+    /// assert!(*data.pointer("/users/0/a~1b").unwrap() == 1);
+    /// assert_eq!(data.pointer("/users/1/name").unwrap_err().to_string(),
+    ///            "JSON Pointer /users/1/name not found, no value at: 1");
+    /// # }
+    /// ```
+    pub fn pointer(&self, pointer: &str) -> JsonResult<&JsonValue> {
+        pointer::get(self, pointer)
+    }
+
+    /// Mutable version of `pointer`.
+    pub fn pointer_mut(&mut self, pointer: &str) -> JsonResult<&mut JsonValue> {
+        pointer::get_mut(self, pointer)
+    }
+
+    /// Inserts a value at the JSON Pointer, the way the `add` operation of
+    /// JSON Patch does. The value it points to doesn't have to exist, but
+    /// its parent does:
+    ///
+    /// - in an object, the key is set, returning the value it held before,
+    /// - in an array, the value is inserted at the index, shifting the rest
+    ///   of the array, and `-` appends it at the end,
+    /// - the empty pointer replaces the whole value.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let mut data = object!{ "list" => array![1, 3] };
+    ///
+    /// data.pointer_insert("/list/1", 2).unwrap();
+    /// data.pointer_insert("/list/-", 4).unwrap();
+    /// data.pointer_insert("/name", "foo").unwrap();
+    ///
+    /// assert_eq!(data, object!{ "list" => array![1, 2, 3, 4], "name" => "foo" });
+    /// assert!(data.pointer_insert("/missing/key", 1).is_err());
+    /// # }
+    /// ```
+    pub fn pointer_insert<T>(&mut self, pointer: &str, value: T) -> JsonResult<Option<JsonValue>>
+    where T: Into<JsonValue> {
+        pointer::insert(self, pointer, value.into())
+    }
+
+    /// Removes the value at the JSON Pointer and returns it. Later members
+    /// of an array are shifted to fill its place. The empty pointer takes
+    /// the whole value, leaving `Null` in its place.
+    pub fn pointer_remove(&mut self, pointer: &str) -> JsonResult<JsonValue> {
+        pointer::remove(self, pointer)
+    }
+
+    /// Compiles a JSONPath query and runs it on the value, see the `query`
+    /// module. Compile the query with `JsonPath::compile` instead when it's
+    /// run more than once.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate json;
+    /// # fn main() {
+    /// let data = array![1, 2, 3, 4, 5];
+    ///
+    /// let nodes = data.query("$[1::2]").unwrap();
+    ///
+    /// assert!(*nodes[0].value() == 2);
+    /// assert_eq!(nodes[1].path(), "$[3]");
+    /// # }
+    /// ```
+    pub fn query(&self, query: &str) -> JsonResult<Vec<Node>> {
+        JsonPath::compile(query).map(|path| path.query(self))
+    }
+}
+
+impl PartialEq for JsonValue {
+    fn eq(&self, other: &JsonValue) -> bool {
+        match *self {
+            JsonValue::Number(value) => match *other {
+                JsonValue::Number(other)  => value == other,
+                JsonValue::Integer(other) => other.eq_f64(value),
+                _                         => false,
+            },
+            JsonValue::Integer(value) => match *other {
+                JsonValue::Integer(other) => value == other,
+                JsonValue::Number(other)  => value.eq_f64(other),
+                _                         => false,
+            },
+            JsonValue::String(ref value) => other.as_str() == Some(value.as_str()),
+            JsonValue::Boolean(value)    => other.as_bool() == Some(value),
+            JsonValue::Null              => other.is_null(),
+            JsonValue::Object(ref value) => match *other {
+                JsonValue::Object(ref other) => value == other,
+                _                            => false,
+            },
+            JsonValue::Array(ref value) => match *other {
+                JsonValue::Array(ref other) => value == other,
+                _                           => false,
+            },
+        }
+    }
 }
 
 /// Implements indexing by `usize` to easily access array members:
@@ -427,7 +721,7 @@ impl<'a> Index<&'a str> for JsonValue {
 
     fn index(&self, index: &str) -> &JsonValue {
         match *self {
-            JsonValue::Object(ref btree) => match btree.get(index) {
+            JsonValue::Object(ref object) => match object.get(index) {
                 Some(value) => value,
                 _ => &NULL
             },
@@ -471,11 +765,11 @@ impl<'a> Index<&'a String> for JsonValue {
 impl<'a> IndexMut<&'a str> for JsonValue {
     fn index_mut(&mut self, index: &str) -> &mut JsonValue {
         match *self {
-            JsonValue::Object(ref mut btree) => {
-                if !btree.contains_key(index) {
-                    btree.insert(index.to_string(), JsonValue::Null);
+            JsonValue::Object(ref mut object) => {
+                if !object.contains_key(index) {
+                    object.insert(index.to_string(), JsonValue::Null);
                 }
-                btree.get_mut(index).unwrap()
+                object.get_mut(index).unwrap()
             },
             _ => {
                 *self = JsonValue::new_object();
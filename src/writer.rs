@@ -0,0 +1,330 @@
+use std::io::{ self, Write, BufWriter };
+use codegen::{ Generator, WriterGenerator, PrettyWriterGenerator };
+use { JsonValue, JsonError, JsonResult, DumpOptions, PrettyOptions, Integer };
+
+#[derive(Clone, Copy, PartialEq)]
+enum Container {
+    Array,
+    Object,
+}
+
+struct Frame {
+    container: Container,
+    empty: bool,
+}
+
+/// Writes JSON into an instance of `std::io::Write` piece by piece, without
+/// building a `JsonValue` first. Output is buffered internally, and either
+/// compact, same as `JsonValue::dump`, or pretty printed, same as
+/// `JsonValue::pretty`.
+///
+/// Calls that would produce invalid JSON, such as writing a key into an
+/// array, closing a container that isn't open or writing a second value at
+/// the top level, fail with `JsonError::InvalidWrite`. So does every call
+/// after the underlying writer has failed, since the output is incomplete.
+/// NaN and infinite numbers rejected with `NonFinite::Error` are checked
+/// before anything is written, and leave the writer as it was.
+///
+/// ```
+/// use json::JsonWriter;
+///
+/// let mut writer = JsonWriter::new(Vec::new());
+///
+/// writer.begin_object().unwrap();
+/// writer.key("name").unwrap();
+/// writer.string("json-rust").unwrap();
+/// writer.key("numbers").unwrap();
+/// writer.begin_array().unwrap();
+/// for number in 1..4 {
+///     writer.integer(number).unwrap();
+/// }
+/// writer.end_array().unwrap();
+/// writer.end_object().unwrap();
+///
+/// let output = writer.finish().unwrap();
+///
+/// assert_eq!(String::from_utf8(output).unwrap(), r#"{"name":"json-rust","numbers":[1,2,3]}"#);
+/// ```
+pub struct JsonWriter<W: Write> {
+    writer: BufWriter<W>,
+    dump: DumpOptions,
+    pretty: Option<PrettyOptions>,
+    stack: Vec<Frame>,
+    dent: u16,
+    after_key: bool,
+    started: bool,
+    failed: bool,
+}
+
+impl<W> JsonWriter<W> where W: Write {
+    /// Writes compact JSON.
+    pub fn new(writer: W) -> Self {
+        JsonWriter::with_options(writer, DumpOptions::new())
+    }
+
+    /// Writes compact JSON using given `DumpOptions`.
+    pub fn with_options(writer: W, options: DumpOptions) -> Self {
+        JsonWriter {
+            writer: BufWriter::new(writer),
+            dump: options,
+            pretty: None,
+            stack: Vec::new(),
+            dent: 0,
+            after_key: false,
+            started: false,
+            failed: false,
+        }
+    }
+
+    /// Writes pretty printed JSON. Takes either a number of spaces to indent
+    /// new blocks with, or `PrettyOptions`. Since the writer can't know how
+    /// long a container is going to be, `PrettyOptions::max_width` has no
+    /// effect and every non-empty container is broken into lines.
+    pub fn pretty<O>(writer: W, options: O) -> Self where O: Into<PrettyOptions> {
+        let mut writer = JsonWriter::new(writer);
+        writer.pretty = Some(options.into());
+        writer
+    }
+
+    pub fn begin_object(&mut self) -> JsonResult<()> {
+        self.begin(Container::Object)
+    }
+
+    pub fn end_object(&mut self) -> JsonResult<()> {
+        self.end(Container::Object)
+    }
+
+    pub fn begin_array(&mut self) -> JsonResult<()> {
+        self.begin(Container::Array)
+    }
+
+    pub fn end_array(&mut self) -> JsonResult<()> {
+        self.end(Container::Array)
+    }
+
+    /// Writes a key of the object that is currently open. Has to be followed
+    /// by its value.
+    pub fn key(&mut self, key: &str) -> JsonResult<()> {
+        try!(self.check_failed());
+
+        match self.stack.last() {
+            Some(&Frame { container: Container::Object, .. }) => {},
+            _ => return Err(JsonError::InvalidWrite("key outside of an object")),
+        }
+
+        if self.after_key {
+            return Err(JsonError::InvalidWrite("key where a value was expected"));
+        }
+
+        try!(self.separate());
+        self.after_key = true;
+
+        self.generate(|gen| {
+            try!(gen.write_key(key));
+            gen.begin_object_value()
+        })
+    }
+
+    /// Writes a whole value at once.
+    pub fn value(&mut self, value: &JsonValue) -> JsonResult<()> {
+        if self.rejects_non_finite() && has_non_finite(value) {
+            return Err(JsonError::NonFiniteNumber);
+        }
+
+        try!(self.before_value());
+        self.generate(|gen| gen.write_json(value))
+    }
+
+    pub fn string(&mut self, value: &str) -> JsonResult<()> {
+        try!(self.before_value());
+        self.generate(|gen| gen.write_string(value))
+    }
+
+    pub fn number(&mut self, value: f64) -> JsonResult<()> {
+        if self.rejects_non_finite() && !value.is_finite() {
+            return Err(JsonError::NonFiniteNumber);
+        }
+
+        try!(self.before_value());
+        self.generate(|gen| gen.write_number(value))
+    }
+
+    /// Writes an integer exactly, including the ones outside of the range
+    /// `f64` can represent without losing precision.
+    pub fn integer<T>(&mut self, value: T) -> JsonResult<()> where T: Into<Integer> {
+        let value = value.into();
+
+        try!(self.before_value());
+        self.generate(|gen| gen.write_integer(value))
+    }
+
+    pub fn boolean(&mut self, value: bool) -> JsonResult<()> {
+        try!(self.before_value());
+        self.generate(|gen| gen.write_bool(value))
+    }
+
+    pub fn null(&mut self) -> JsonResult<()> {
+        try!(self.before_value());
+        self.generate(|gen| gen.write_null())
+    }
+
+    /// Flushes the output written so far to the underlying writer.
+    pub fn flush(&mut self) -> JsonResult<()> {
+        self.writer.flush().map_err(JsonError::from)
+    }
+
+    /// Checks that the document is complete, flushes the output and returns
+    /// the underlying writer.
+    pub fn finish(mut self) -> JsonResult<W> {
+        try!(self.check_failed());
+
+        if !self.started {
+            return Err(JsonError::InvalidWrite("nothing was written"));
+        }
+
+        if !self.stack.is_empty() {
+            return Err(JsonError::InvalidWrite("unclosed array or object"));
+        }
+
+        try!(self.generate(|gen| gen.end_document()));
+
+        self.writer.into_inner().map_err(|err| JsonError::from(err.into_error()))
+    }
+
+    // Runs `write` with a generator positioned where the output left off
+    fn generate<F>(&mut self, write: F) -> JsonResult<()>
+    where F: for<'b> FnOnce(&mut dyn Generator<T = &'b mut BufWriter<W>>) -> io::Result<()> {
+        let mut writer = &mut self.writer;
+
+        let result = match self.pretty {
+            Some(options) => {
+                write(&mut PrettyWriterGenerator::at_depth(writer, options, self.dent))
+            },
+            None => {
+                write(&mut WriterGenerator::new(&mut writer, self.dump))
+            }
+        };
+
+        if result.is_err() {
+            self.failed = true;
+        }
+
+        result.map_err(JsonError::from)
+    }
+
+    fn check_failed(&self) -> JsonResult<()> {
+        if self.failed {
+            return Err(JsonError::InvalidWrite("writing after an earlier write failed"));
+        }
+
+        Ok(())
+    }
+
+    fn rejects_non_finite(&self) -> bool {
+        match self.pretty {
+            Some(options) => options.dump_options().rejects_non_finite(),
+            None          => self.dump.rejects_non_finite(),
+        }
+    }
+
+    // Writes what goes before an item of the container that is currently
+    // open, ie: a comma and a line break.
+    fn separate(&mut self) -> JsonResult<()> {
+        let first = match self.stack.last_mut() {
+            Some(frame) => {
+                let first = frame.empty;
+                frame.empty = false;
+                first
+            },
+            None => return Ok(())
+        };
+
+        let container = self.stack[self.stack.len() - 1].container;
+
+        try!(self.generate(|gen| match container {
+            Container::Array  => gen.begin_array_value(first),
+            Container::Object => gen.begin_object_key(first),
+        }));
+
+        // The generator has indented, keep track of it
+        if first {
+            self.dent += 1;
+        }
+
+        Ok(())
+    }
+
+    fn before_value(&mut self) -> JsonResult<()> {
+        try!(self.check_failed());
+
+        match self.stack.last().map(|frame| frame.container) {
+            None => {
+                if self.started {
+                    return Err(JsonError::InvalidWrite("more than one value at the top level"));
+                }
+
+                self.started = true;
+                Ok(())
+            },
+            Some(Container::Array) => self.separate(),
+            Some(Container::Object) => {
+                if !self.after_key {
+                    return Err(JsonError::InvalidWrite("value where a key was expected"));
+                }
+
+                self.after_key = false;
+                Ok(())
+            }
+        }
+    }
+
+    fn begin(&mut self, container: Container) -> JsonResult<()> {
+        try!(self.before_value());
+
+        self.stack.push(Frame {
+            container: container,
+            empty: true,
+        });
+
+        self.generate(|gen| match container {
+            Container::Array  => gen.begin_array(),
+            Container::Object => gen.begin_object(),
+        })
+    }
+
+    fn end(&mut self, container: Container) -> JsonResult<()> {
+        try!(self.check_failed());
+
+        let empty = match self.stack.last() {
+            Some(frame) if frame.container == container => frame.empty,
+            Some(_) => return Err(JsonError::InvalidWrite("closing a different container than the open one")),
+            None    => return Err(JsonError::InvalidWrite("closing a container that isn't open")),
+        };
+
+        if self.after_key {
+            return Err(JsonError::InvalidWrite("closing an object after a key without a value"));
+        }
+
+        self.stack.pop();
+
+        try!(self.generate(|gen| match container {
+            Container::Array  => gen.end_array(empty),
+            Container::Object => gen.end_object(empty),
+        }));
+
+        if !empty {
+            self.dent -= 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn has_non_finite(value: &JsonValue) -> bool {
+    match *value {
+        JsonValue::Number(num)        => !num.is_finite(),
+        JsonValue::Array(ref vec)     => vec.iter().any(has_non_finite),
+        JsonValue::Object(ref object) => object.iter().any(|(_, value)| has_non_finite(value)),
+        _                             => false,
+    }
+}
@@ -4,10 +4,14 @@ extern crate json;
 mod unit {
     use super::json;
 
-    use std::f64;
+    use std::{ env, f64, io, i64, u64, i128, u128 };
     use std::collections::BTreeMap;
     use std::collections::HashMap;
-    use json::{ stringify, stringify_pretty, parse, JsonValue, JsonError, Null };
+    use json::{ stringify, stringify_pretty, parse, parse_with, ParseOptions, JsonValue, JsonResult, JsonError, ErrorKind, Null, Integer, JsonPath };
+    use json::{ DumpOptions, PrettyOptions, Indent, LineEnding, NonFinite, JsonWriter, Theme, Color };
+    use json::SummaryOptions;
+    use json::object::{ Object, Entry };
+    use json::codegen::{ Generator, DumpGenerator, PrettyGenerator, WriterGenerator, PrettyWriterGenerator };
 
     #[test]
     fn is_as_string() {
@@ -160,6 +164,63 @@ mod unit {
         assert_eq!(JsonValue::Number(f64::NEG_INFINITY).dump(), "null");
     }
 
+    #[test]
+    fn stringify_non_finite_literal() {
+        let data = array![f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1];
+        let options = DumpOptions::new().non_finite(NonFinite::Literal);
+
+        assert_eq!(data.dump_with(options).unwrap(), "[NaN,Infinity,-Infinity,1]");
+        assert_eq!(data.dump_with(DumpOptions::new()).unwrap(), "[null,null,null,1]");
+
+        let options = PrettyOptions::new().non_finite(NonFinite::Literal);
+        assert_eq!(data.pretty(options.indent(Indent::None)), "[NaN, Infinity, -Infinity, 1]");
+        assert_eq!(data.pretty(options.max_width(80)), "[NaN, Infinity, -Infinity, 1]");
+        assert_eq!(data.pretty(options.max_width(10)), "[\n    NaN,\n    Infinity,\n    -Infinity,\n    1\n]");
+    }
+
+    #[test]
+    fn stringify_non_finite_error() {
+        let data = object!{ "ok" => 1, "value" => f64::INFINITY };
+        let options = DumpOptions::new().non_finite(NonFinite::Error);
+
+        let err = data.dump_with(options).unwrap_err();
+        assert_eq!(err, JsonError::NonFiniteNumber);
+        assert_eq!(err.kind(), ErrorKind::Type);
+        assert_eq!(err.code(), "non_finite_number");
+
+        let mut buf = Vec::new();
+        let err = data.to_writer_with(&mut buf, options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(JsonError::from(err), JsonError::NonFiniteNumber);
+
+        for &width in &[0, 80] {
+            let options = PrettyOptions::new().non_finite(NonFinite::Error).max_width(width);
+            let err = data.to_writer_pretty(&mut Vec::new(), options).unwrap_err();
+            assert_eq!(JsonError::from(err), JsonError::NonFiniteNumber);
+        }
+
+        let options = PrettyOptions::new().non_finite(NonFinite::Error);
+        let err = data.to_writer_pretty(&mut Vec::new(), options).unwrap_err();
+        assert_eq!(JsonError::from(err), JsonError::NonFiniteNumber);
+
+        // Infallible output writes `null` instead of failing
+        let numbers = array![1, f64::NAN];
+
+        for &(width, expected) in &[(0, "[\n  1,\n  null\n]"), (80, "[1, null]")] {
+            let options = PrettyOptions::from(2).non_finite(NonFinite::Error).max_width(width);
+            assert_eq!(numbers.pretty_with(options), Err(JsonError::NonFiniteNumber));
+            assert_eq!(numbers.pretty(options), expected);
+            assert_eq!(numbers.serialized_len_pretty(options), expected.len());
+            assert_eq!(stringify_pretty(numbers.clone(), options), expected);
+        }
+
+        let options = PrettyOptions::from(2).non_finite(NonFinite::Error);
+        assert_eq!(array![1, 2].pretty_with(options), Ok("[\n  1,\n  2\n]".to_string()));
+
+        let options = DumpOptions::new().non_finite(NonFinite::Error);
+        assert_eq!(object!{ "ok" => 1 }.dump_with(options), Ok(r#"{"ok":1}"#.to_string()));
+    }
+
     #[test]
     fn stringify_negative_zero() {
         assert_eq!(JsonValue::Number(-0f64).dump(), "-0");
@@ -229,6 +290,18 @@ mod unit {
         assert_eq!(stringify(array), "[1,null,2,null,3]");
     }
 
+    // The order of object entries depends on the map backend. Keys below are
+    // inserted in sorted order, so that it's the same with the default one
+    // and `preserve_order`, while with `hash_map` only the parsed values can
+    // be compared.
+    fn assert_json_eq(json: &str, expected: &str) {
+        if cfg!(all(feature = "hash_map", not(feature = "preserve_order"))) {
+            assert_eq!(parse(json).unwrap(), parse(expected).unwrap());
+        } else {
+            assert_eq!(json, expected);
+        }
+    }
+
     #[test]
     fn stringify_object() {
         let object = object!{
@@ -236,7 +309,12 @@ mod unit {
             "age" => 30
         };
 
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
         assert_eq!(stringify(object), r#"{"age":30,"name":"Maciej"}"#);
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(stringify(object), r#"{"name":"Maciej","age":30}"#);
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&stringify(object.clone())).unwrap(), object);
     }
 
     #[test]
@@ -246,7 +324,10 @@ mod unit {
         object.insert("name".into(), "Maciej".into());
         object.insert("age".into(), 30.into());
 
+        #[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
         assert_eq!(stringify(object), r#"{"age":30,"name":"Maciej"}"#);
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&stringify(object)).unwrap(), object!{ "age" => 30, "name" => "Maciej" });
     }
 
     #[test]
@@ -256,7 +337,11 @@ mod unit {
         object.insert("name".into(), "Maciej".into());
         object.insert("age".into(), 30.into());
 
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
         assert_eq!(stringify(object), r#"{"age":30,"name":"Maciej"}"#);
+        // Entries of a `HashMap` come out in no particular order
+        #[cfg(any(feature = "preserve_order", feature = "hash_map"))]
+        assert_eq!(parse(&stringify(object)).unwrap(), object!{ "age" => 30, "name" => "Maciej" });
     }
 
     #[test]
@@ -266,7 +351,10 @@ mod unit {
         object["a"] = 100.into();
         object["b"] = false.into();
 
+        #[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
         assert_eq!(stringify(object), r#"{"a":100,"b":false}"#);
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&stringify(object.clone())).unwrap(), object);
     }
 
     #[test]
@@ -301,6 +389,24 @@ mod unit {
         assert_eq!(stringify("foo\u{1f}bar\u{0}baz"), r#""foo\u001fbar\u0000baz""#);
     }
 
+    #[test]
+    fn stringify_long_strings_with_escapes() {
+        for len in 1..80 {
+            for pos in 0..len {
+                for &(ch, escaped) in &[('"', "\\\""), ('\\', "\\\\"), ('\n', "\\n"), ('\u{1}', "\\u0001"), ('\u{1f}', "\\u001f")] {
+                    let mut string = "é".repeat(len);
+                    let mut expected = string.clone();
+
+                    string.insert(pos * 2, ch);
+                    expected.insert_str(pos * 2, escaped);
+
+                    assert_eq!(stringify(string.as_str()), format!("\"{}\"", expected));
+                    assert_eq!(parse(&stringify(string.as_str())).unwrap(), string.as_str());
+                }
+            }
+        }
+    }
+
     #[test]
     fn stringify_pretty_object() {
         let object = object!{
@@ -313,10 +419,219 @@ mod unit {
             "cars" => array![ "Golf", "Mercedes", "Porsche" ]
         };
 
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
         assert_eq!(stringify_pretty(object, 2),
                    "{\n  \"age\": 50,\n  \"cars\": [\n    \"Golf\",\n    \"Mercedes\",\n    \
                     \"Porsche\"\n  ],\n  \"name\": \"Urlich\",\n  \"parents\": {\n    \"father\": \
                     \"Brutus\",\n    \"mother\": \"Helga\"\n  }\n}");
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(stringify_pretty(object, 2),
+                   "{\n  \"name\": \"Urlich\",\n  \"age\": 50,\n  \"parents\": {\n    \"mother\": \
+                    \"Helga\",\n    \"father\": \"Brutus\"\n  },\n  \"cars\": [\n    \"Golf\",\n    \
+                    \"Mercedes\",\n    \"Porsche\"\n  ]\n}");
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&stringify_pretty(object.clone(), 2)).unwrap(), object);
+    }
+
+    #[test]
+    fn stringify_pretty_options_tabs_crlf() {
+        let options = PrettyOptions::new()
+            .indent(Indent::Tab)
+            .line_ending(LineEnding::CrLf)
+            .trailing_newline(true);
+
+        let data = object!{ "a" => array![1, object!{ "b" => Null }] };
+
+        assert_eq!(
+            stringify_pretty(data, options),
+            "{\r\n\t\"a\": [\r\n\t\t1,\r\n\t\t{\r\n\t\t\t\"b\": null\r\n\t\t}\r\n\t]\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn stringify_pretty_options_single_line() {
+        let data = object!{ "a" => array![1, 2], "b" => object!{ "c" => true } };
+
+        let options = PrettyOptions::new().indent(Indent::None);
+        assert_json_eq(&data.pretty(options), r#"{"a": [1, 2], "b": {"c": true}}"#);
+
+        let options = options.space_after_comma(false);
+        assert_json_eq(&data.pretty(options), r#"{"a": [1,2],"b": {"c": true}}"#);
+
+        let options = options.space_after_colon(false);
+        assert_eq!(data.pretty(options), data.dump());
+    }
+
+    #[test]
+    fn stringify_pretty_options_expanded_empty() {
+        let data = object!{ "a" => array![], "b" => object!{} };
+        let options = PrettyOptions::from(2).compact_empty(false);
+
+        assert_json_eq(&data.pretty(options), "{\n  \"a\": [\n  ],\n  \"b\": {\n  }\n}");
+        assert_eq!(array![].pretty(options), "[\n]");
+        assert_eq!(array![].pretty(2), "[]");
+    }
+
+    #[test]
+    fn stringify_pretty_options_large_indent() {
+        let data = array![array![1]];
+
+        assert_eq!(data.pretty(40), format!("[\n{0}[\n{0}{0}1\n{0}]\n]", " ".repeat(40)));
+    }
+
+    #[test]
+    fn stringify_pretty_max_width() {
+        let data = array![
+            array![1, 2, 3],
+            object!{ "short" => true },
+            object!{ "long" => "this string will not fit", "other" => array![1, 2] },
+            array![]
+        ];
+
+        let options = PrettyOptions::from(2).max_width(30);
+
+        assert_json_eq(&data.pretty(options), r#"[
+  [1, 2, 3],
+  {"short": true},
+  {
+    "long": "this string will not fit",
+    "other": [1, 2]
+  },
+  []
+]"#);
+
+        let options = options.max_width(200);
+        assert_json_eq(&data.pretty(options), r#"[[1, 2, 3], {"short": true}, {"long": "this string will not fit", "other": [1, 2]}, []]"#);
+
+        let options = options.max_width(0);
+        assert_eq!(data.pretty(options), data.pretty(2));
+    }
+
+    #[test]
+    fn stringify_pretty_max_width_exact_fit() {
+        // `["a", "b"]` is 10 characters wide, with the trailing comma 11
+        let data = array![array!["a", "b"], 1];
+
+        let fits = PrettyOptions::from(2).max_width(13);
+        assert_eq!(data.pretty(fits), "[\n  [\"a\", \"b\"],\n  1\n]");
+
+        let breaks = PrettyOptions::from(2).max_width(12);
+        assert_eq!(data.pretty(breaks), "[\n  [\n    \"a\",\n    \"b\"\n  ],\n  1\n]");
+    }
+
+    #[test]
+    fn stringify_pretty_max_width_unicode() {
+        // Width is counted in characters, not bytes
+        let data = array![array!["żółć"], array!["\u{1}"]];
+        let options = PrettyOptions::from(2).max_width(11);
+
+        assert_eq!(data.pretty(options), "[\n  [\"żółć\"],\n  [\n    \"\\u0001\"\n  ]\n]");
+    }
+
+    #[test]
+    fn writer_generator_pretty_max_width() {
+        let data = object!{ "a" => array![1, 2, 3], "b" => array!["x".repeat(50)] };
+        let options = PrettyOptions::new().max_width(40).trailing_newline(true);
+
+        let mut buf = Vec::new();
+        data.to_writer_pretty(&mut buf, options).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(options));
+        assert_eq!(parse(&data.pretty(options)).unwrap(), data);
+    }
+
+    #[test]
+    fn stringify_ascii_only() {
+        let data = array!["ąćę", "\u{80}\u{ffff}", "𝄞 clef", "\n\"", "plain"];
+        let options = DumpOptions::new().ascii_only(true);
+        let dump = data.dump_with(options).unwrap();
+
+        assert_eq!(dump, r#"["\u0105\u0107\u0119","\u0080\uffff","\ud834\udd1e clef","\n\"","plain"]"#);
+        assert!(dump.is_ascii());
+        assert_eq!(parse(&dump).unwrap(), data);
+
+        let mut buf = Vec::new();
+        data.to_writer_with(&mut buf, options).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), dump);
+    }
+
+    #[test]
+    fn stringify_pretty_ascii_only() {
+        let data = object!{ "ключ" => array!["значение", "😀"] };
+
+        let options = PrettyOptions::from(2).ascii_only(true);
+        assert_eq!(data.pretty(options), "{\n  \"\\u043a\\u043b\\u044e\\u0447\": [\n    \"\\u0437\\u043d\\u0430\\u0447\\u0435\\u043d\\u0438\\u0435\",\n    \"\\ud83d\\ude00\"\n  ]\n}");
+
+        // Escapes count towards the line width
+        let options = PrettyOptions::from(2).ascii_only(true).max_width(40);
+        assert_eq!(data.pretty(options), data.pretty(PrettyOptions::from(2).ascii_only(true)));
+
+        let options = PrettyOptions::from(2).ascii_only(true).max_width(98);
+        assert_eq!(data.pretty(options), r#"{"\u043a\u043b\u044e\u0447": ["\u0437\u043d\u0430\u0447\u0435\u043d\u0438\u0435", "\ud83d\ude00"]}"#);
+    }
+
+    #[test]
+    fn stringify_html_safe() {
+        let data = object!{
+            "script" => "</script><script>alert('x' && 1)</script>",
+            "separators" => "a\u{2028}b\u{2029}c",
+            "<key>" => "ünïcödé stays"
+        };
+
+        let options = DumpOptions::new().html_safe(true);
+        let dump = data.dump_with(options).unwrap();
+
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
+        assert_eq!(dump, r#"{"\u003ckey\u003e":"ünïcödé stays","script":"\u003c/script\u003e\u003cscript\u003ealert(\u0027x\u0027 \u0026\u0026 1)\u003c/script\u003e","separators":"a\u2028b\u2029c"}"#);
+        #[cfg(any(feature = "preserve_order", feature = "hash_map"))]
+        {
+            assert!(dump.contains(r#""\u003ckey\u003e":"ünïcödé stays""#));
+            assert!(dump.contains(r#""script":"\u003c/script\u003e\u003cscript\u003ealert(\u0027x\u0027 \u0026\u0026 1)\u003c/script\u003e""#));
+            assert!(dump.contains(r#""separators":"a\u2028b\u2029c""#));
+        }
+        assert_eq!(parse(&dump).unwrap(), data);
+
+        let mut buf = Vec::new();
+        data.to_writer_with(&mut buf, options).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), dump);
+
+        let dump = data.dump_with(options.ascii_only(true)).unwrap();
+        assert!(dump.is_ascii());
+        assert!(!dump.contains('<'));
+        assert_eq!(parse(&dump).unwrap(), data);
+    }
+
+    #[test]
+    fn stringify_pretty_html_safe() {
+        let data = array!["<b>", array!["&"]];
+
+        for &width in &[0, 20, 80] {
+            let options = PrettyOptions::from(2).html_safe(true).max_width(width);
+            let pretty = data.pretty(options);
+
+            assert!(!pretty.contains('<') && !pretty.contains('&'));
+            assert_eq!(parse(&pretty).unwrap(), data);
+        }
+
+        assert_eq!(data.pretty(PrettyOptions::new().html_safe(true).max_width(29)), r#"["\u003cb\u003e", ["\u0026"]]"#);
+        assert_eq!(data.pretty(PrettyOptions::new().html_safe(true).max_width(28)), "[\n    \"\\u003cb\\u003e\",\n    [\"\\u0026\"]\n]");
+    }
+
+    #[test]
+    fn writer_generator_pretty() {
+        let data = object!{
+            "foo" => array!["bar", 100, true],
+            "baz" => object!{}
+        };
+
+        let options = PrettyOptions::new().indent(Indent::Tab).trailing_newline(true);
+
+        let mut buf = Vec::new();
+        data.to_writer_pretty(&mut buf, options).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(options));
+
+        let mut buf = Vec::new();
+        data.to_writer_pretty(&mut buf, 4).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(4));
     }
 
     #[test]
@@ -397,6 +712,704 @@ mod unit {
         assert_eq!(parse("18446744073709551616").unwrap(), 18446744073709552000f64);
     }
 
+    #[test]
+    fn parse_integer_exact() {
+        let data = parse("[9007199254740993, 18446744073709551615, -9223372036854775808, 0, 1.0]").unwrap();
+
+        assert!(data[0].is_integer());
+        assert!(data[0] == 9007199254740993u64);
+        assert!(data[0] != 9007199254740992u64);
+        assert!(data[0] != 9007199254740992.0);
+        assert_eq!(data[1].as_u64(), Some(u64::MAX));
+        assert_eq!(data[2].as_i64(), Some(i64::MIN));
+        assert!(data[3].is_integer());
+        assert!(!data[4].is_integer());
+        assert_eq!(data.dump(), "[9007199254740993,18446744073709551615,-9223372036854775808,0,1]");
+    }
+
+    #[test]
+    fn parse_integer_128() {
+        let data = parse("[18446744073709551616, -9223372036854775809, 340282366920938463463374607431768211455, -170141183460469231731687303715884105728]").unwrap();
+
+        assert!(data.members().all(|number| number.is_integer()));
+        assert_eq!(data[0].as_u128(), Some(1 << 64));
+        assert_eq!(data[1].as_i128(), Some(i64::MIN as i128 - 1));
+        assert_eq!(data[2].as_u128(), Some(u128::MAX));
+        assert_eq!(data[3].as_i128(), Some(i128::MIN));
+        assert_eq!(data[1].as_i64(), None);
+        assert_eq!(data.dump(), "[18446744073709551616,-9223372036854775809,340282366920938463463374607431768211455,-170141183460469231731687303715884105728]");
+    }
+
+    #[test]
+    fn parse_integer_out_of_range() {
+        let data = parse("[340282366920938463463374607431768211456, -170141183460469231731687303715884105729, 123456789012345678901234567890.5e2]").unwrap();
+
+        assert!(data.members().all(|number| !number.is_integer()));
+        assert_eq!(data[0], JsonValue::Number(340282366920938463463374607431768211456.0));
+        assert_eq!(data[1], JsonValue::Number(-170141183460469231731687303715884105728.0));
+        assert!((data[2].as_f64().unwrap() / 1.2345678901234e31 - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn integer_128() {
+        let big = JsonValue::from(u128::MAX);
+        let small = JsonValue::from(i128::MIN);
+
+        assert!(big == u128::MAX);
+        assert!(big != u128::MAX - 1);
+        assert!(small == i128::MIN);
+        assert_eq!(big.as_i128(), None);
+        assert_eq!(big.as_u64(), None);
+        assert_eq!(small.as_u128(), None);
+        assert_eq!(JsonValue::from(5i128), JsonValue::from(5u8));
+        assert_eq!(JsonValue::from(5u128).as_u8(), Some(5));
+        assert_eq!(JsonValue::from(1u128 << 100), JsonValue::from(1267650600228229401496703205376.0));
+        assert_eq!(big.dump(), "340282366920938463463374607431768211455");
+        assert_eq!(parse(&small.dump()).unwrap(), small);
+    }
+
+    #[test]
+    fn integer_and_float_equality() {
+        assert_eq!(JsonValue::from(1), JsonValue::from(1.0));
+        assert_eq!(parse("[1.0, 2e0]").unwrap(), array![1, 2]);
+        assert_ne!(JsonValue::from(u64::MAX), JsonValue::from(18446744073709551615.0));
+        assert_eq!(JsonValue::from(i64::MIN), JsonValue::from(-9223372036854775808.0));
+        assert_ne!(JsonValue::from(1), JsonValue::from(1.5));
+        assert!(JsonValue::from(42.0) == 42u8);
+    }
+
+    #[test]
+    fn integer_accessors() {
+        let max = JsonValue::from(u64::MAX);
+        let negative = JsonValue::from(-1);
+
+        assert_eq!(max.as_u64(), Some(u64::MAX));
+        assert_eq!(max.as_i64(), None);
+        assert_eq!(max.as_f64(), Some(18446744073709551615.0));
+        assert_eq!(negative.as_i8(), Some(-1));
+        assert_eq!(negative.as_u64(), None);
+        assert_eq!(JsonValue::from(300).as_u8(), None);
+        assert_eq!(JsonValue::from(300).as_u16(), Some(300));
+        assert!(JsonValue::from(0u64).is_empty());
+        assert!(max.is_number());
+    }
+
+    #[test]
+    fn exact_accessors() {
+        assert_eq!(JsonValue::from(255).as_u8_exact(), Ok(255));
+        assert_eq!(JsonValue::from(-128).as_i8_exact(), Ok(-128));
+        assert_eq!(JsonValue::from(3.0).as_usize_exact(), Ok(3));
+        assert_eq!(JsonValue::from(-0.0).as_u32_exact(), Ok(0));
+        assert_eq!(JsonValue::from(1e20).as_u128_exact(), Ok(100000000000000000000));
+        assert_eq!(JsonValue::from(u128::MAX).as_u128_exact(), Ok(u128::MAX));
+        assert_eq!(JsonValue::from(i128::MIN).as_i128_exact(), Ok(i128::MIN));
+        assert_eq!(JsonValue::from(i64::MIN).as_i64_exact(), Ok(i64::MIN));
+        assert_eq!(parse("9007199254740993").unwrap().as_u64_exact(), Ok(9007199254740993));
+
+        assert_eq!(JsonValue::from(1.9).as_i64_exact(), Err(JsonError::FractionalNumber {
+            number: "1.9".into(),
+            target: "i64",
+        }));
+        assert_eq!(JsonValue::from(256).as_u8_exact(), Err(JsonError::NumberOutOfRange {
+            number: "256".into(),
+            target: "u8",
+        }));
+        assert_eq!(JsonValue::from(1e300).as_i128_exact(), Err(JsonError::NumberOutOfRange {
+            number: "1e300".into(),
+            target: "i128",
+        }));
+        assert_eq!(JsonValue::from(f64::NAN).as_i32_exact(), Err(JsonError::NumberOutOfRange {
+            number: "NaN".into(),
+            target: "i32",
+        }));
+        assert_eq!(JsonValue::from(u64::MAX).as_i64_exact().unwrap_err().kind(), ErrorKind::Type);
+        assert_eq!(JsonValue::from(-1).as_u16_exact().unwrap_err().code(), "number_out_of_range");
+        assert_eq!(JsonValue::from(0.5).as_isize_exact().unwrap_err().code(), "fractional_number");
+        assert_eq!(JsonValue::from("1").as_u64_exact(), Err(JsonError::wrong_type("Number")));
+        assert_eq!(Null.as_i16_exact(), Err(JsonError::wrong_type("Number")));
+    }
+
+    #[test]
+    fn stringify_integer_extremes() {
+        assert_eq!(JsonValue::from(u64::MAX).dump(), "18446744073709551615");
+        assert_eq!(JsonValue::from(i64::MIN).dump(), "-9223372036854775808");
+        assert_eq!(format!("{}", JsonValue::from(u64::MAX)), "18446744073709551615");
+        assert_eq!(format!("{:?}", JsonValue::from(7)), "Integer(7)");
+    }
+
+    #[test]
+    fn pointer_get() {
+        let data = parse(r#"{
+            "foo": ["bar", "baz"],
+            "": 0,
+            "a/b": 1,
+            "m~n": 8,
+            " ": 7,
+            "nested": { "list": [{ "id": 10 }] }
+        }"#).unwrap();
+
+        assert!(*data.pointer("").unwrap() == data);
+        assert!(*data.pointer("/foo").unwrap() == array!["bar", "baz"]);
+        assert!(*data.pointer("/foo/0").unwrap() == "bar");
+        assert!(*data.pointer("/").unwrap() == 0);
+        assert!(*data.pointer("/a~1b").unwrap() == 1);
+        assert!(*data.pointer("/m~0n").unwrap() == 8);
+        assert!(*data.pointer("/ ").unwrap() == 7);
+        assert!(*data.pointer("/nested/list/0/id").unwrap() == 10);
+    }
+
+    #[test]
+    fn pointer_errors() {
+        let mut data = object!{ "foo" => array![1, 2], "bar" => "baz" };
+
+        assert_eq!(data.pointer("foo"), Err(JsonError::InvalidPointer("foo".into())));
+        assert_eq!(data.pointer("/foo~2"), Err(JsonError::InvalidPointer("/foo~2".into())));
+        assert_eq!(data.pointer("/foo~").unwrap_err().kind(), ErrorKind::Syntax);
+
+        assert_eq!(data.pointer("/qux/0"), Err(JsonError::PointerNotFound {
+            pointer: "/qux/0".into(),
+            segment: "qux".into(),
+        }));
+        assert_eq!(data.pointer("/foo/2"), Err(JsonError::PointerNotFound {
+            pointer: "/foo/2".into(),
+            segment: "2".into(),
+        }));
+        assert_eq!(data.pointer("/bar/x").unwrap_err().code(), "pointer_not_found");
+        assert!(data.pointer("/foo/01").is_err());
+        assert!(data.pointer("/foo/-").is_err());
+        assert!(data.pointer("/foo/+1").is_err());
+        assert!(data.pointer_mut("/foo/-").is_err());
+        assert!(data.pointer_remove("/foo/-").is_err());
+        assert!(data.pointer_insert("/foo/3", 3).is_err());
+        assert!(data.pointer_insert("/bar/x", 3).is_err());
+        assert!(data.pointer_insert("/a/b~", 3).is_err());
+
+        assert_eq!(data, object!{ "foo" => array![1, 2], "bar" => "baz" });
+    }
+
+    #[test]
+    fn pointer_modify() {
+        let mut data = object!{ "foo" => array![1, 3], "bar" => object!{} };
+
+        *data.pointer_mut("/foo/1").unwrap() = 4.into();
+        assert_eq!(data.pointer_insert("/foo/1", 2), Ok(None));
+        assert_eq!(data.pointer_insert("/foo/3", 5), Ok(None));
+        assert_eq!(data.pointer_insert("/foo/-", 6), Ok(None));
+        assert_eq!(data.pointer_insert("/bar/a~1b", true), Ok(None));
+        assert_eq!(data.pointer_insert("/bar/a~1b", false), Ok(Some(true.into())));
+
+        assert_eq!(data, object!{
+            "foo" => array![1, 2, 4, 5, 6],
+            "bar" => object!{ "a/b" => false }
+        });
+
+        assert_eq!(data.pointer_remove("/foo/0"), Ok(1.into()));
+        assert_eq!(data.pointer_remove("/bar/a~1b"), Ok(false.into()));
+        assert_eq!(data.pointer_remove("/bar/a~1b").unwrap_err().code(), "pointer_not_found");
+
+        assert_eq!(data, object!{ "foo" => array![2, 4, 5, 6], "bar" => object!{} });
+
+        let old = data.clone();
+
+        assert_eq!(data.pointer_insert("", "root"), Ok(Some(old)));
+        assert_eq!(data.pointer_remove(""), Ok("root".into()));
+        assert!(data.is_null());
+    }
+
+    fn bookstore() -> JsonValue {
+        parse(r#"{ "store": {
+            "book": [
+                { "category": "reference", "author": "Nigel Rees", "title": "Sayings of the Century", "price": 8.95 },
+                { "category": "fiction", "author": "Evelyn Waugh", "title": "Sword of Honour", "price": 12.99 },
+                { "category": "fiction", "author": "Herman Melville", "title": "Moby Dick", "isbn": "0-553-21311-3", "price": 8.99 },
+                { "category": "fiction", "author": "J. R. R. Tolkien", "title": "The Lord of the Rings", "isbn": "0-395-19395-8", "price": 22.99 }
+            ],
+            "bicycle": { "color": "red", "price": 399 }
+        } }"#).unwrap()
+    }
+
+    fn query_values(data: &JsonValue, query: &str) -> Vec<JsonValue> {
+        data.query(query).unwrap().iter().map(|node| node.value().clone()).collect()
+    }
+
+    fn query_paths(data: &JsonValue, query: &str) -> Vec<String> {
+        data.query(query).unwrap().iter().map(|node| node.path().to_string()).collect()
+    }
+
+    #[test]
+    fn query_bookstore() {
+        let data = bookstore();
+
+        assert_eq!(query_values(&data, "$.store.book[*].author"), vec![
+            JsonValue::from("Nigel Rees"),
+            "Evelyn Waugh".into(),
+            "Herman Melville".into(),
+            "J. R. R. Tolkien".into(),
+        ]);
+        assert_eq!(query_values(&data, "$..author").len(), 4);
+        assert_eq!(query_values(&data, "$.store.*").len(), 2);
+        assert_eq!(query_values(&data, "$..book[2].author"), vec![JsonValue::from("Herman Melville")]);
+        assert!(query_values(&data, "$..book[2].publisher").is_empty());
+        assert_eq!(query_values(&data, "$..book[-1].title"), vec![JsonValue::from("The Lord of the Rings")]);
+        assert_eq!(query_paths(&data, "$..book[0,1]"), query_paths(&data, "$..book[:2]"));
+        assert_eq!(query_paths(&data, "$..book[?@.isbn]"), vec![
+            "$['store']['book'][2]",
+            "$['store']['book'][3]",
+        ]);
+        assert_eq!(query_paths(&data, "$..book[?@.price<10]"), vec![
+            "$['store']['book'][0]",
+            "$['store']['book'][2]",
+        ]);
+        assert_eq!(query_values(&data, "$..*").len(), 27);
+
+        let mut prices = query_values(&data, "$.store..price");
+        prices.sort_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap());
+        assert_eq!(JsonValue::from(prices), parse("[8.95, 8.99, 12.99, 22.99, 399]").unwrap());
+    }
+
+    #[test]
+    fn query_selectors() {
+        let data = parse(r#"{
+            "o": { "j j": { "k.k": 3 } },
+            "'": { "@": 2 },
+            "a": [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            "ąę": true
+        }"#).unwrap();
+
+        assert_eq!(query_values(&data, "$.o['j j']"), vec![object!{ "k.k" => 3 }]);
+        assert_eq!(query_values(&data, "$.o[\"j j\"]['k.k']"), vec![JsonValue::from(3)]);
+        assert_eq!(query_values(&data, "$['\\'']['@']"), vec![JsonValue::from(2)]);
+        assert_eq!(query_values(&data, "$['\\u0027'].*"), vec![JsonValue::from(2)]);
+        assert_eq!(query_values(&data, "$.ąę"), vec![JsonValue::from(true)]);
+        assert_eq!(query_values(&data, "$ .a [ 1 , -1 ]"), vec![JsonValue::from(1), 9.into()]);
+        assert!(query_values(&data, "$.a[10]").is_empty());
+        assert!(query_values(&data, "$.a[-11]").is_empty());
+        assert_eq!(query_values(&data, "$.a[0, 0]"), vec![JsonValue::from(0), 0.into()]);
+        assert!(query_values(&data, "$.o[0]").is_empty());
+        assert!(query_values(&data, "$.a.b").is_empty());
+        assert_eq!(query_values(&data, "$"), vec![data.clone()]);
+        assert_eq!(query_paths(&data, "$['\\'']"), vec!["$['\\'']"]);
+    }
+
+    #[test]
+    fn query_slices() {
+        let data = array![0, 1, 2, 3, 4, 5, 6];
+
+        assert_eq!(query_values(&data, "$[1:3]"), vec![JsonValue::from(1), 2.into()]);
+        assert_eq!(query_values(&data, "$[5:]"), vec![JsonValue::from(5), 6.into()]);
+        assert_eq!(query_values(&data, "$[1:5:2]"), vec![JsonValue::from(1), 3.into()]);
+        assert_eq!(query_values(&data, "$[5:1:-2]"), vec![JsonValue::from(5), 3.into()]);
+        assert_eq!(query_values(&data, "$[::-1]").len(), 7);
+        assert_eq!(query_values(&data, "$[::-1]")[0], 6);
+        assert_eq!(query_values(&data, "$[-2:]"), vec![JsonValue::from(5), 6.into()]);
+        assert_eq!(query_values(&data, "$[:-5]"), vec![JsonValue::from(0), 1.into()]);
+        assert_eq!(query_values(&data, "$[-100:100:3]"), vec![JsonValue::from(0), 3.into(), 6.into()]);
+        assert!(query_values(&data, "$[1:5:0]").is_empty());
+        assert!(query_values(&data, "$[3:1]").is_empty());
+        assert_eq!(query_paths(&data, "$[ 4 : 6 : 1 ]"), vec!["$[4]", "$[5]"]);
+        assert!(query_values(&JsonValue::from("abc"), "$[0:2]").is_empty());
+    }
+
+    #[test]
+    fn query_filters() {
+        let data = parse(r#"[
+            { "a": 1, "b": 1.0 },
+            { "a": "x", "b": "y" },
+            { "a": [1, { "c": 2 }], "b": [1.0, { "c": 2 }] },
+            { "a": null },
+            { "c": 5 },
+            { "a": 9007199254740993, "b": 9007199254740992.0 }
+        ]"#).unwrap();
+
+        assert_eq!(query_paths(&data, "$[?@.a == @.b]"), vec!["$[0]", "$[2]", "$[4]"]);
+        assert_eq!(query_paths(&data, "$[?@.a != @.b]"), vec!["$[1]", "$[3]", "$[5]"]);
+        assert_eq!(query_paths(&data, "$[?@.a < @.b]"), vec!["$[1]"]);
+        assert_eq!(query_paths(&data, "$[?@.a > @.b]"), vec!["$[5]"]);
+        assert_eq!(query_paths(&data, "$[?@.a <= @.b]"), vec!["$[0]", "$[1]", "$[2]", "$[4]"]);
+        assert_eq!(query_paths(&data, "$[?@.a == null]"), vec!["$[3]"]);
+        assert_eq!(query_paths(&data, "$[?@.a]"), vec!["$[0]", "$[1]", "$[2]", "$[3]", "$[5]"]);
+        assert_eq!(query_paths(&data, "$[?!@.a]"), vec!["$[4]"]);
+        assert_eq!(query_paths(&data, "$[?@.a[1].c == 2]"), vec!["$[2]"]);
+        assert_eq!(query_paths(&data, "$[?@.a == 1 || @.c >= 5]"), vec!["$[0]", "$[4]"]);
+        assert_eq!(query_paths(&data, "$[?@.a && !(@.a == 'x' || @.a == null)]"), vec!["$[0]", "$[2]", "$[5]"]);
+        assert_eq!(query_paths(&data, "$[?@.a == $[0].b]"), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, "$[?@.a == 1e0 && @.b == 100e-2]"), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, "$[?@.a < 'y']"), vec!["$[1]"]);
+        assert!(query_paths(&data, "$[?@.a < true]").is_empty());
+        assert_eq!(query_paths(&array![0.5, 1, 1.5], "$[?@ > 0.9 && @ < 1.1]"), vec!["$[1]"]);
+
+        let mut paths = query_paths(&data, "$..[?@.c]");
+
+        paths.sort();
+        assert_eq!(paths, vec!["$[2]['a'][1]", "$[2]['b'][1]", "$[4]"]);
+    }
+
+    #[test]
+    fn query_functions() {
+        let data = parse(r#"[
+            { "a": "ąęć", "b": [1, 2, 3] },
+            { "a": [1, 2], "b": { "x": 1 } },
+            { "a": 3, "b": [4] }
+        ]"#).unwrap();
+
+        assert_eq!(query_paths(&data, "$[?length(@.a) == 3]"), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, "$[?length(@.b) == 1]"), vec!["$[1]", "$[2]"]);
+        assert_eq!(query_paths(&data, "$[?length(@.a) == length(@.b)]"), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, "$[?length(@.c) == length(@.d)]").len(), 3);
+        assert_eq!(query_paths(&data, "$[?count(@.*) == 2]").len(), 3);
+        assert_eq!(query_paths(&data, "$[?count(@.b[*]) > 2]"), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, "$[?value(@.b[*]) == 4]"), vec!["$[2]"]);
+        assert_eq!(query_paths(&data, "$[?value(@..x) == 1]"), vec!["$[1]"]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn query_regex_functions() {
+        let data = array!["1974-05-01", "1974-05-1", "ab\rc", "x1974-05-01"];
+
+        assert_eq!(query_paths(&data, r#"$[?match(@, '1974-05-..')]"#), vec!["$[0]"]);
+        assert_eq!(query_paths(&data, r#"$[?search(@, '1974-05-..')]"#), vec!["$[0]", "$[3]"]);
+        assert!(query_paths(&data, r#"$[?match(@, 'ab.c')]"#).is_empty());
+        assert_eq!(query_paths(&data, r#"$[?match(@, 'ab[\r]c')]"#), vec!["$[2]"]);
+        assert_eq!(query_paths(&data, r#"$[?!search(@, 'x|\\r')]"#), vec!["$[0]", "$[1]"]);
+        assert_eq!(query_paths(&data, r#"$[?match(@, $[0])]"#), vec!["$[0]"]);
+        assert!(query_paths(&data, r#"$[?match(@, '[')]"#).is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn query_regex_syntax() {
+        let matches = |pattern: &str, value: &str| {
+            let query = format!("$[?match(@, {})]", stringify(pattern));
+
+            !query_paths(&array![value], &query).is_empty()
+        };
+
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "a\rc"));
+        assert!(!matches("a.c", "a\nc"));
+        assert!(matches("(a|b)*c", "abac"));
+        assert!(matches("a{2}b{1,}c{1,2}", "aabbbcc"));
+        assert!(matches("[a-c]+[^a]", "abcd"));
+        assert!(matches("\\p{Lu}\\p{Ll}*\\P{L}", "Hello!"));
+        assert!(matches("[\\p{N}x-]+", "1x-2"));
+        assert!(matches("[-a][a-]", "-a"));
+        assert!(matches("\\.\\n\\(\\^", ".\n(^"));
+
+        // `&`, `~` and `^` are characters in I-Regexp classes
+        assert!(matches("[a&&b]", "&"));
+        assert!(matches("[a~~b]", "~"));
+        assert!(matches("[a^]", "^"));
+
+        // Not I-Regexp, even though the `regex` crate would understand them
+        for &(pattern, value) in &[
+            ("\\d", "1"), ("\\w", "a"), ("\\s", " "), ("a\\b", "a"),
+            ("(?i)a", "a"), ("(?:a)", "a"), ("^a", "a"), ("a$", "a"),
+            ("a*?", "a"), ("a+?", "a"), ("a??", "a"), ("a{1}?", "a"),
+            ("a{,2}", "a"), ("*a", "a"), ("a)", "a"), ("(a", "a"),
+            ("[[a]]", "a"), ("[]", "a"), ("[a-b-c]", "a"), ("\\p{Greek}", "α"),
+            ("\\p{Lx}", "a"), ("a\\", "a"), ("a]", "a]"), ("a}", "a}"),
+        ] {
+            assert!(!matches(pattern, value), "{} matched {}", pattern, value);
+        }
+
+        let data = parse(r#"[
+            { "value": "ab", "pattern": "a." },
+            { "value": "ac", "pattern": "a." },
+            { "value": "xab", "pattern": "a." },
+            { "value": "1", "pattern": "\\d" }
+        ]"#).unwrap();
+
+        assert_eq!(query_paths(&data, "$[?match(@.value, @.pattern)]"), vec!["$[0]", "$[1]"]);
+
+        // The same pattern is compiled separately for `match` and `search`
+        assert_eq!(query_paths(&data, "$[?search(@.value, @.pattern) && !match(@.value, @.pattern)]"), vec!["$[2]"]);
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn query_regex_functions_disabled() {
+        let err = JsonPath::compile("$[?match(@, 'a')]").unwrap_err();
+
+        assert_eq!(err.code(), "invalid_query");
+        assert_eq!(err.to_string(), "Invalid JSONPath query at offset 3: `match` and `search` need the `regex` feature");
+    }
+
+    #[test]
+    fn query_errors() {
+        let offset = |query: &str| match JsonPath::compile(query) {
+            Err(JsonError::InvalidQuery { offset, .. }) => offset,
+            other => panic!("{} compiled: {:?}", query, other),
+        };
+
+        assert_eq!(offset(""), 0);
+        assert_eq!(offset("@.a"), 0);
+        assert_eq!(offset("$ "), 1);
+        assert_eq!(offset("$."), 2);
+        assert_eq!(offset("$.1a"), 2);
+        assert_eq!(offset("$[01]"), 2);
+        assert_eq!(offset("$[-0]"), 3);
+        assert_eq!(offset("$[9007199254740992]"), 2);
+        assert_eq!(offset("$['a'"), 5);
+        assert_eq!(offset("$['a\\q']"), 5);
+        assert_eq!(offset("$['\\ud800']"), 5);
+        assert_eq!(offset("$[?1]"), 3);
+        assert_eq!(offset("$[?@.* == 1]"), 3);
+        assert_eq!(offset("$[?1 == @..a]"), 8);
+        assert_eq!(offset("$[?length(@.a)]"), 3);
+        assert_eq!(offset("$[?count(1) == 1]"), 9);
+        assert_eq!(offset("$[?length(@.*) == 1]"), 10);
+        assert_eq!(offset("$[?length(@.a, @.b) == 1]"), 3);
+        assert_eq!(offset("$[?foo(@.a)]"), 3);
+        assert_eq!(offset("$[?@.a == tru]"), 10);
+        assert_eq!(offset("$[?(@.a]"), 7);
+        assert_eq!(offset(&format!("$[?{}@{}]", "(".repeat(100), ")".repeat(100))), 67);
+
+        let err = JsonPath::compile("$[?@ = 1]").unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Syntax);
+        assert_eq!(err.code(), "invalid_query");
+        assert_eq!(err.to_string(), "Invalid JSONPath query at offset 5: Expected `,` or `]`");
+        assert!("$.a".parse::<JsonPath>().is_ok());
+    }
+
+    #[test]
+    fn query_nested_functions() {
+        let reason = |query: &str| match JsonPath::compile(query) {
+            Err(JsonError::InvalidQuery { reason, .. }) => reason,
+            other => panic!("compiled: {:?}", other.is_ok()),
+        };
+
+        // Arguments used to be parsed twice, doubling the time with every
+        // level of nesting
+        let mut nested = String::from("@");
+
+        for _ in 0..30 {
+            nested = format!("length({}) == 1", nested);
+        }
+
+        let start = ::std::time::Instant::now();
+
+        assert_eq!(reason(&format!("$[?{}]", nested)), "Expected a value");
+        assert!(start.elapsed().as_secs() < 5);
+
+        let mut nested = String::from("$[?count(@) == length(");
+
+        for _ in 0..30 {
+            nested.push_str("value(");
+        }
+
+        assert_eq!(reason(&nested), "Expected a query, literal or function");
+
+        let deep = format!("$[?{}@{} == 1]", "length(".repeat(200000), ")".repeat(200000));
+
+        assert_eq!(reason(&deep), "Query nested too deep");
+        assert_eq!(reason(&format!("$[?{}@.a{}]", "count(".repeat(100), ")".repeat(100))), "Query nested too deep");
+
+        let nested = format!("$[?{}@{} == 1]", "length(".repeat(60), ")".repeat(60));
+
+        assert!(JsonPath::compile(&nested).is_ok());
+    }
+
+    fn patched(data: &str, patch: &str) -> JsonResult<JsonValue> {
+        let mut data = parse(data).unwrap();
+
+        json::apply_patch(&mut data, &parse(patch).unwrap()).map(|_| data)
+    }
+
+    #[test]
+    fn patch_operations() {
+        assert_eq!(patched(r#"{ "foo": "bar" }"#, r#"[{ "op": "add", "path": "/baz", "value": "qux" }]"#),
+                   Ok(object!{ "foo" => "bar", "baz" => "qux" }));
+        assert_eq!(patched(r#"{ "foo": ["bar", "baz"] }"#, r#"[{ "op": "add", "path": "/foo/1", "value": "qux" }]"#),
+                   Ok(object!{ "foo" => array!["bar", "qux", "baz"] }));
+        assert_eq!(patched(r#"{ "foo": ["bar"] }"#, r#"[{ "op": "add", "path": "/foo/-", "value": ["abc"] }]"#),
+                   Ok(object!{ "foo" => array!["bar", array!["abc"]] }));
+        assert_eq!(patched(r#"{ "foo": "bar" }"#, r#"[{ "op": "add", "path": "", "value": [1] }]"#),
+                   Ok(array![1]));
+        assert_eq!(patched(r#"{ "foo": "bar", "baz": "qux" }"#, r#"[{ "op": "remove", "path": "/baz" }]"#),
+                   Ok(object!{ "foo" => "bar" }));
+        assert_eq!(patched(r#"{ "foo": ["bar", "qux", "baz"] }"#, r#"[{ "op": "remove", "path": "/foo/1" }]"#),
+                   Ok(object!{ "foo" => array!["bar", "baz"] }));
+        assert_eq!(patched(r#"{ "baz": "qux", "foo": "bar" }"#, r#"[{ "op": "replace", "path": "/baz", "value": null }]"#),
+                   Ok(object!{ "baz" => Null, "foo" => "bar" }));
+        assert_eq!(patched(r#"{ "foo": { "bar": 1 }, "qux": {} }"#, r#"[{ "op": "move", "from": "/foo/bar", "path": "/qux/thud" }]"#),
+                   Ok(object!{ "foo" => object!{}, "qux" => object!{ "thud" => 1 } }));
+        assert_eq!(patched(r#"{ "foo": ["all", "grass", "cows", "eat"] }"#, r#"[{ "op": "move", "from": "/foo/1", "path": "/foo/3" }]"#),
+                   Ok(object!{ "foo" => array!["all", "cows", "eat", "grass"] }));
+        assert_eq!(patched(r#"{ "foo": { "bar": 1 } }"#, r#"[{ "op": "move", "from": "/foo", "path": "/foo" }]"#),
+                   Ok(object!{ "foo" => object!{ "bar" => 1 } }));
+        assert_eq!(patched(r#"{ "foo": [1], "bar": {} }"#, r#"[{ "op": "copy", "from": "/foo", "path": "/bar/foo" }]"#),
+                   Ok(object!{ "foo" => array![1], "bar" => object!{ "foo" => array![1] } }));
+        assert_eq!(patched(r#"{ "baz": "qux", "foo": ["a", 2, "c"] }"#, r#"[
+                       { "op": "test", "path": "/baz", "value": "qux" },
+                       { "op": "test", "path": "/foo/1", "value": 2.0 },
+                       { "op": "test", "path": "", "value": { "foo": ["a", 2, "c"], "baz": "qux" } }
+                   ]"#),
+                   Ok(object!{ "baz" => "qux", "foo" => array!["a", 2, "c"] }));
+        assert_eq!(patched(r#"{}"#, r#"[]"#), Ok(object!{}));
+    }
+
+    #[test]
+    fn patch_errors() {
+        use std::error::Error;
+
+        assert_eq!(patched(r#"{ "baz": "qux" }"#, r#"[{ "op": "test", "path": "/baz", "value": "bar" }]"#),
+                   Err(JsonError::PatchFailed {
+                       index: 0,
+                       op: Some("test".into()),
+                       reason: Box::new(JsonError::TestFailed("/baz".into())),
+                   }));
+        assert_eq!(patched(r#"{ "foo": "bar" }"#, r#"[{ "op": "add", "path": "/baz/bat", "value": "qux" }]"#),
+                   Err(JsonError::PatchFailed {
+                       index: 0,
+                       op: Some("add".into()),
+                       reason: Box::new(JsonError::PointerNotFound {
+                           pointer: "/baz/bat".into(),
+                           segment: "baz".into(),
+                       }),
+                   }));
+        assert_eq!(patched(r#"{ "foo": 1 }"#, r#"[{ "op": "inc", "path": "/foo" }]"#).unwrap_err().to_string(),
+                   "JSON Patch operation 0 (inc) failed: Invalid JSON Patch: Unknown operation");
+        assert_eq!(patched(r#"{ "foo": 1 }"#, r#"[1]"#).unwrap_err().to_string(),
+                   "JSON Patch operation 0 failed: Invalid JSON Patch: Operation must be an object");
+        assert_eq!(patched(r#"{ "foo": 1 }"#, r#"{ "op": "remove", "path": "/foo" }"#),
+                   Err(JsonError::InvalidPatch("Patch must be an array of operations")));
+        assert_eq!(patched(r#"{ "foo": { "bar": 1 } }"#, r#"[{ "op": "move", "from": "/foo", "path": "/foo/bar/baz" }]"#).unwrap_err().to_string(),
+                   "JSON Patch operation 0 (move) failed: Invalid JSON Patch: Can't move a value into itself");
+        assert_eq!(patched(r#"{ "foo": 1 }"#, r#"[{ "op": "replace", "path": "/bar", "value": 2 }]"#).unwrap_err().code(),
+                   "patch_failed");
+
+        let err = patched(r#"[1, 2]"#, r#"[{ "op": "remove", "path": "/5" }]"#).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Type);
+        assert_eq!(err.source().unwrap().to_string(), "JSON Pointer /5 not found, no value at: 5");
+        assert_eq!(patched(r#"[]"#, r#"[{ "op": "test", "path": "", "value": {} }]"#).unwrap_err().kind(), ErrorKind::Type);
+        assert_eq!(patched(r#"[]"#, r#"[{ "op": "copy", "path": "/-" }]"#).unwrap_err().kind(), ErrorKind::Syntax);
+        assert_eq!(patched(r#"[]"#, r#"[{ "op": "add", "path": "-", "value": 1 }]"#).unwrap_err().kind(), ErrorKind::Syntax);
+    }
+
+    #[test]
+    fn patch_malformed_operations() {
+        let failure = |patch: &str| {
+            let err = patched(r#"{ "foo": 1 }"#, patch).unwrap_err();
+
+            assert_eq!(err.kind(), ErrorKind::Syntax);
+
+            err.to_string()
+        };
+
+        assert_eq!(failure(r#"[{ "path": "/foo" }]"#),
+                   "JSON Patch operation 0 failed: Invalid JSON Patch: `op` is missing");
+        assert_eq!(failure(r#"[{ "op": true, "path": "/foo" }]"#),
+                   "JSON Patch operation 0 failed: Invalid JSON Patch: `op` must be a string");
+        assert_eq!(failure(r#"[{ "op": "remove" }]"#),
+                   "JSON Patch operation 0 (remove) failed: Invalid JSON Patch: `path` is missing");
+        assert_eq!(failure(r#"[{ "op": "remove", "path": 1 }]"#),
+                   "JSON Patch operation 0 (remove) failed: Invalid JSON Patch: `path` must be a string");
+        assert_eq!(failure(r#"[{ "op": "add", "path": "/bar" }]"#),
+                   "JSON Patch operation 0 (add) failed: Invalid JSON Patch: `value` is missing");
+        assert_eq!(failure(r#"[{ "op": "test", "path": "/foo" }]"#),
+                   "JSON Patch operation 0 (test) failed: Invalid JSON Patch: `value` is missing");
+        assert_eq!(failure(r#"[{ "op": "remove", "path": "/foo" }, { "op": "copy", "path": "/bar", "from": null }]"#),
+                   "JSON Patch operation 1 (copy) failed: Invalid JSON Patch: `from` must be a string");
+        assert_eq!(failure(r#"[{ "op": "move", "path": "/bar" }]"#),
+                   "JSON Patch operation 0 (move) failed: Invalid JSON Patch: `from` is missing");
+
+        let err = patched(r#"{}"#, r#"[{ "op": ["add"], "path": "" }]"#).unwrap_err();
+
+        assert_eq!(err, JsonError::PatchFailed {
+            index: 0,
+            op: None,
+            reason: Box::new(JsonError::InvalidPatchMember { member: "op", reason: "must be a string" }),
+        });
+        assert_eq!(err.code(), "patch_failed");
+
+        match err {
+            JsonError::PatchFailed { reason, .. } => assert_eq!(reason.code(), "invalid_patch_member"),
+            _                                     => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn patch_is_atomic() {
+        let mut data = object!{ "list" => array![1, 2, 3], "name" => "foo" };
+        let original = data.clone();
+
+        let patch = parse(r#"[
+            { "op": "remove", "path": "/list/0" },
+            { "op": "add", "path": "/list/-", "value": 4 },
+            { "op": "replace", "path": "/name", "value": "bar" },
+            { "op": "move", "from": "/name", "path": "/title" },
+            { "op": "test", "path": "/list/0", "value": 1 }
+        ]"#).unwrap();
+
+        assert_eq!(json::apply_patch(&mut data, &patch).unwrap_err(), JsonError::PatchFailed {
+            index: 4,
+            op: Some("test".into()),
+            reason: Box::new(JsonError::TestFailed("/list/0".into())),
+        });
+        assert_eq!(data, original);
+        assert_eq!(data.dump(), original.dump());
+    }
+
+    #[test]
+    fn patch_rollback_restores_every_operation() {
+        let mut data = parse(r#"{
+            "z": 1, "a": 2, "m": [1, 2, 3], "k/~": { "x": 1, "y": 2 }, "": 3
+        }"#).unwrap();
+
+        // Enough keys for the ordered map to look entries up by index
+        for index in 0..20 {
+            data["many"][format!("key{}", 19 - index)] = index.into();
+        }
+
+        let original = data.clone();
+
+        let patch = parse(r#"[
+            { "op": "remove", "path": "/a" },
+            { "op": "add", "path": "/new", "value": 1 },
+            { "op": "add", "path": "/z", "value": "replaced" },
+            { "op": "add", "path": "/m/1", "value": 9 },
+            { "op": "add", "path": "/m/-", "value": 10 },
+            { "op": "remove", "path": "/m/0" },
+            { "op": "replace", "path": "/k~1~0/x", "value": 7 },
+            { "op": "move", "from": "/k~1~0/x", "path": "/k~1~0/y" },
+            { "op": "move", "from": "/m/0", "path": "/m/-" },
+            { "op": "move", "from": "/many/key10", "path": "/many/key0" },
+            { "op": "remove", "path": "/many/key15" },
+            { "op": "copy", "from": "/m", "path": "/" },
+            { "op": "move", "from": "/", "path": "/missing/key" }
+        ]"#).unwrap();
+
+        assert_eq!(json::apply_patch(&mut data, &patch).unwrap_err(), JsonError::PatchFailed {
+            index: 12,
+            op: Some("move".into()),
+            reason: Box::new(JsonError::PointerNotFound {
+                pointer: "/missing/key".into(),
+                segment: "missing".into(),
+            }),
+        });
+        assert_eq!(data, original);
+        assert_eq!(data.dump(), original.dump());
+    }
+
+    #[test]
+    fn patch_rollback_restores_root() {
+        let mut data = array![1, 2];
+        let original = data.clone();
+
+        let patch = parse(r#"[
+            { "op": "replace", "path": "", "value": { "a": 1 } },
+            { "op": "add", "path": "/b", "value": 2 },
+            { "op": "move", "from": "/a", "path": "" },
+            { "op": "test", "path": "", "value": 2 }
+        ]"#).unwrap();
+
+        assert!(json::apply_patch(&mut data, &patch).is_err());
+        assert_eq!(data, original);
+    }
+
     #[test]
     fn parse_array() {
         assert_eq!(parse(r#"[10, "foo", true, null]"#).unwrap(), array![
@@ -573,6 +1586,63 @@ mod unit {
         assert!(err.is_err());
     }
 
+    #[test]
+    fn parse_long_strings() {
+        for len in 0..80 {
+            let plain = "aé€".chars().cycle().take(len).collect::<String>();
+
+            assert_eq!(parse(&format!("\"{}\"", plain)).unwrap(), plain.as_str());
+        }
+    }
+
+    #[test]
+    fn parse_long_strings_with_escapes() {
+        for len in 1..80 {
+            for pos in 0..len {
+                let mut expected = "a".repeat(len);
+                let mut source = expected.clone();
+
+                expected.insert(pos, '\n');
+                source.insert_str(pos, "\\n");
+
+                assert_eq!(parse(&format!("\"{}\"", source)).unwrap(), expected.as_str());
+
+                expected.remove(pos);
+                expected.insert(pos, '"');
+                source.remove(pos + 1);
+                source.insert(pos + 1, '"');
+
+                assert_eq!(parse(&format!("\"{}\"", source)).unwrap(), expected.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn parse_long_strings_with_control_characters() {
+        for len in 1..80 {
+            for pos in 0..len {
+                for &ch in &['\u{0}', '\u{1f}', '\t'] {
+                    let mut source = "a".repeat(len);
+                    source.insert(pos, ch);
+
+                    match parse(&format!("\"{}\"", source)).unwrap_err() {
+                        JsonError::UnexpectedCharacter { ch: found, offset, .. } => {
+                            assert_eq!(found, ch);
+                            assert_eq!(offset, pos + 1);
+                        },
+                        err => panic!("Unexpected error: {:?}", err),
+                    }
+                }
+            }
+
+            let source = format!("\"{}", "a".repeat(len));
+            match parse(&source).unwrap_err() {
+                JsonError::UnexpectedEndOfJson { offset, .. } => assert_eq!(offset, len + 1),
+                err => panic!("Unexpected error: {:?}", err),
+            }
+        }
+    }
+
     #[test]
     fn array_len() {
         let data = array![0, 1, 2, 3];
@@ -689,57 +1759,182 @@ mod unit {
     }
 
     #[test]
-    fn object_entries() {
-        let data = object!{
-            "a" => 1,
-            "b" => "foo"
-        };
+    fn object_map_api() {
+        let mut object = Object::new();
 
-        for (_, value) in data.entries() {
-            assert!(!value.is_null());
-        }
+        assert!(object.is_empty());
+        assert_eq!(object.insert("foo".into(), 1.into()), None);
+        assert_eq!(object.insert("bar".into(), 2.into()), None);
+        assert_eq!(object.insert("foo".into(), 3.into()), Some(1.into()));
+        assert_eq!(object.len(), 2);
+        assert!(object.contains_key("bar"));
+        assert_eq!(object.get("foo"), Some(&JsonValue::from(3)));
 
-        let mut entries = data.entries();
+        *object.get_mut("bar").unwrap() = "baz".into();
 
-        let (key, value) = entries.next().unwrap();
-        assert_eq!(key, "a");
-        assert_eq!(value, 1);
+        assert_eq!(object.remove("bar"), Some("baz".into()));
+        assert_eq!(object.remove("bar"), None);
+        assert_eq!(object.get("bar"), None);
 
-        let (key, value) = entries.next().unwrap();
-        assert_eq!(key, "b");
-        assert_eq!(value, "foo");
+        let entries: Vec<(String, JsonValue)> = object.clone().into_iter().collect();
 
-        assert!(entries.next().is_none());
+        assert_eq!(entries, vec![("foo".to_string(), JsonValue::from(3))]);
+
+        object.clear();
+
+        assert!(object.is_empty());
     }
 
     #[test]
-    fn object_entries_rev() {
-        let data = object!{
-            "a" => 1,
-            "b" => "foo"
-        };
+    fn object_entry() {
+        let mut object = Object::new();
 
-        for (_, value) in data.entries().rev() {
-            assert!(!value.is_null());
+        match object.entry("foo".into()) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), "foo");
+                *entry.insert(1.into()) = 2.into();
+            },
+            Entry::Occupied(_) => panic!("Entry should be vacant"),
         }
 
-        let mut entries = data.entries().rev();
+        match object.entry("foo".into()) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), "foo");
+                assert_eq!(*entry.get(), 2);
+                assert_eq!(entry.insert(3.into()), 2);
+                assert_eq!(entry.remove(), 3);
+            },
+            Entry::Vacant(_) => panic!("Entry should be occupied"),
+        }
 
-        let (key, value) = entries.next().unwrap();
-        assert_eq!(key, "b");
-        assert_eq!(value, "foo");
+        assert!(object.is_empty());
 
-        let (key, value) = entries.next().unwrap();
-        assert_eq!(key, "a");
-        assert_eq!(value, 1);
+        *object.entry("list".into()).or_insert_with(JsonValue::new_array) = array![1];
+        object.entry("list".into()).or_insert(Null).push(2).unwrap();
 
-        assert!(entries.next().is_none());
+        assert_eq!(JsonValue::Object(object), object!{ "list" => array![1, 2] });
     }
 
     #[test]
-    fn object_entries_mut() {
-        let mut data = object!{
-            "a" => Null,
+    fn object_from_maps() {
+        let mut btree = BTreeMap::new();
+        let mut hash = HashMap::new();
+
+        for (index, key) in ["a", "b", "c"].iter().enumerate() {
+            btree.insert(key.to_string(), JsonValue::from(index));
+            hash.insert(key.to_string(), JsonValue::from(index));
+        }
+
+        assert_eq!(Object::from(btree), Object::from(hash));
+    }
+
+    #[test]
+    #[cfg(feature = "preserve_order")]
+    fn object_preserve_order() {
+        let mut data = json::parse(r#"{"foo":1,"bar":2,"baz":3}"#).unwrap();
+
+        data["answer"] = 42.into();
+        data["bar"] = "replaced".into();
+
+        assert_eq!(data.dump(), r#"{"foo":1,"bar":"replaced","baz":3,"answer":42}"#);
+
+        data.remove("foo");
+
+        assert_eq!(data.dump(), r#"{"bar":"replaced","baz":3,"answer":42}"#);
+        assert_eq!(data, object!{ "answer" => 42, "baz" => 3, "bar" => "replaced" });
+    }
+
+    #[test]
+    #[cfg(feature = "preserve_order")]
+    fn object_preserve_order_many_keys() {
+        let mut data = json::JsonValue::new_object();
+
+        for number in (0..40).rev() {
+            data[number.to_string().as_str()] = number.into();
+        }
+
+        for number in (0..40).filter(|number| number % 3 == 0) {
+            assert_eq!(data.remove(&number.to_string()), number);
+        }
+
+        let keys: Vec<&str> = data.entries().map(|(key, _)| key.as_str()).collect();
+        let expected: Vec<String> = (0..40).rev().filter(|number| number % 3 != 0).map(|number| number.to_string()).collect();
+
+        assert_eq!(keys, expected);
+
+        for number in 0..40 {
+            if number % 3 == 0 {
+                assert!(data[number.to_string().as_str()].is_null());
+            } else {
+                assert_eq!(data[number.to_string().as_str()], number);
+            }
+        }
+    }
+
+    #[test]
+    fn object_entries() {
+        let data = object!{
+            "a" => 1,
+            "b" => "foo"
+        };
+
+        for (_, value) in data.entries() {
+            assert!(!value.is_null());
+        }
+
+        #[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+        {
+            let mut entries = data.entries();
+
+            let (key, value) = entries.next().unwrap();
+            assert_eq!(key, "a");
+            assert_eq!(value, 1);
+
+            let (key, value) = entries.next().unwrap();
+            assert_eq!(key, "b");
+            assert_eq!(value, "foo");
+
+            assert!(entries.next().is_none());
+        }
+        // Entries of a `HashMap` come out in no particular order
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        {
+            let mut entries: Vec<_> = data.entries().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            assert_eq!(entries, vec![(&"a".to_string(), &JsonValue::from(1)), (&"b".to_string(), &JsonValue::from("foo"))]);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
+    fn object_entries_rev() {
+        let data = object!{
+            "a" => 1,
+            "b" => "foo"
+        };
+
+        for (_, value) in data.entries().rev() {
+            assert!(!value.is_null());
+        }
+
+        let mut entries = data.entries().rev();
+
+        let (key, value) = entries.next().unwrap();
+        assert_eq!(key, "b");
+        assert_eq!(value, "foo");
+
+        let (key, value) = entries.next().unwrap();
+        assert_eq!(key, "a");
+        assert_eq!(value, 1);
+
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn object_entries_mut() {
+        let mut data = object!{
+            "a" => Null,
             "b" => Null
         };
 
@@ -755,6 +1950,7 @@ mod unit {
     }
 
     #[test]
+    #[cfg(any(feature = "preserve_order", not(feature = "hash_map")))]
     fn object_entries_mut_rev() {
         let mut data = object!{
             "a" => Null,
@@ -781,7 +1977,12 @@ mod unit {
             "age" => 30
         };
 
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
         assert_eq!(object.dump(), "{\"age\":30,\"name\":\"Maciej\"}");
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(object.dump(), "{\"name\":\"Maciej\",\"age\":30}");
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&object.dump()).unwrap(), object);
     }
 
     #[test]
@@ -796,10 +1997,18 @@ mod unit {
             "cars" => array![ "Golf", "Mercedes", "Porsche" ]
         };
 
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
         assert_eq!(object.pretty(2),
                    "{\n  \"age\": 50,\n  \"cars\": [\n    \"Golf\",\n    \"Mercedes\",\n    \
                     \"Porsche\"\n  ],\n  \"name\": \"Urlich\",\n  \"parents\": {\n    \"father\": \
                     \"Brutus\",\n    \"mother\": \"Helga\"\n  }\n}");
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(object.pretty(2),
+                   "{\n  \"name\": \"Urlich\",\n  \"age\": 50,\n  \"parents\": {\n    \"mother\": \
+                    \"Helga\",\n    \"father\": \"Brutus\"\n  },\n  \"cars\": [\n    \"Golf\",\n    \
+                    \"Mercedes\",\n    \"Porsche\"\n  ]\n}");
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        assert_eq!(parse(&object.pretty(2)).unwrap(), object);
     }
 
     #[test]
@@ -922,8 +2131,46 @@ mod unit {
             "answer" => 42
         };
 
-        assert_eq!(format!("{}", data), r#"{"answer":42,"foo":"bar"}"#);
-        assert_eq!(format!("{:#}", data), "{\n    \"answer\": 42,\n    \"foo\": \"bar\"\n}");
+        #[cfg(not(any(feature = "preserve_order", feature = "hash_map")))]
+        {
+            assert_eq!(format!("{}", data), r#"{"answer":42,"foo":"bar"}"#);
+            assert_eq!(format!("{:#}", data), "{\n    \"answer\": 42,\n    \"foo\": \"bar\"\n}");
+        }
+        #[cfg(feature = "preserve_order")]
+        {
+            assert_eq!(format!("{}", data), r#"{"foo":"bar","answer":42}"#);
+            assert_eq!(format!("{:#}", data), "{\n    \"foo\": \"bar\",\n    \"answer\": 42\n}");
+        }
+        #[cfg(all(feature = "hash_map", not(feature = "preserve_order")))]
+        {
+            assert_eq!(parse(&format!("{}", data)).unwrap(), data);
+            assert_eq!(parse(&format!("{:#}", data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn fmt_matches_dump_and_pretty() {
+        let data = object!{
+            "escaped" => "quote \" backslash \\ control \u{1}",
+            "unicode" => "zażółć 🦄",
+            "mixed" => "ż\"ł\n🦄\u{1f}é\\",
+            "nested" => array![1.5, -0.0, Null, object!{ "deep" => array![array![]] }],
+            "empty" => object!{}
+        };
+
+        assert_eq!(format!("{}", data), data.dump());
+        assert_eq!(format!("{:#}", data), data.pretty(4));
+    }
+
+    #[test]
+    fn fmt_write_into_string() {
+        use std::fmt::Write;
+
+        let mut buffer = String::from("payload=");
+
+        write!(buffer, "{}", array!["foo", 42]).unwrap();
+
+        assert_eq!(buffer, r#"payload=["foo",42]"#);
     }
 
     #[test]
@@ -934,6 +2181,9 @@ mod unit {
             ch: 'X',
             line: 3,
             column: 4,
+            offset: 5,
+            path: "$".into(),
+            expected: &["null"],
         });
 
         assert_eq!(format!("{}", err), "Unexpected character: X at (3:4)");
@@ -947,6 +2197,9 @@ mod unit {
             ch: '🦄',
             line: 3,
             column: 4,
+            offset: 5,
+            path: "$".into(),
+            expected: &["null"],
         });
 
         assert_eq!(format!("{}", err), "Unexpected character: 🦄 at (3:4)");
@@ -960,11 +2213,235 @@ mod unit {
             ch: ']',
             line: 4,
             column: 3,
+            offset: 17,
+            path: "$[1]".into(),
+            expected: &["value"],
         });
 
         assert_eq!(format!("{}", err), "Unexpected character: ] at (4:3)");
     }
 
+    #[test]
+    fn error_position_at_line_start() {
+        let err = parse("[\n1,\n]").unwrap_err();
+
+        match err {
+            JsonError::UnexpectedCharacter { line, column, offset, .. } => {
+                assert_eq!((line, column, offset), (3, 1, 5));
+            },
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_position_newline_in_literal() {
+        let err = parse("[\r\n  tr\nue]").unwrap_err();
+
+        match err {
+            JsonError::UnexpectedCharacter { ch, line, column, .. } => {
+                assert_eq!((ch, line, column), ('\n', 2, 5));
+            },
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_position_after_end() {
+        let err = parse("{}\n\n \n  x").unwrap_err();
+
+        match err {
+            JsonError::UnexpectedCharacter { ch, line, column, .. } => {
+                assert_eq!((ch, line, column), ('x', 4, 3));
+            },
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_unexpected_two_byte_character() {
+        let err = parse("[1, é]").unwrap_err();
+
+        assert_eq!(err, JsonError::UnexpectedCharacter {
+            ch: 'é',
+            line: 1,
+            column: 5,
+            offset: 4,
+            path: "$[1]".into(),
+            expected: &["value"],
+        });
+    }
+
+    #[test]
+    fn error_unexpected_end_of_json() {
+        let err = parse("{\n  \"foo\": [1, 2").unwrap_err();
+
+        assert_eq!(err, JsonError::UnexpectedEndOfJson {
+            line: 2,
+            column: 15,
+            offset: 16,
+            path: "$['foo']".into(),
+            expected: &["','", "']'"],
+        });
+
+        assert_eq!(format!("{}", err), "Unexpected end of JSON at (2:15)");
+    }
+
+    #[test]
+    fn error_path() {
+        let err = parse(r#"{"foo":{"it's":[true,{"bar":nul}]}}"#).unwrap_err();
+
+        match err {
+            JsonError::UnexpectedCharacter { ref path, offset, .. } => {
+                assert_eq!(path, r#"$['foo']['it\'s'][1]['bar']"#);
+                assert_eq!(offset, 31);
+            },
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_path_escaped_key() {
+        let err = parse(r#"{"a\nb\u0041":[x]}"#).unwrap_err();
+
+        match err {
+            JsonError::UnexpectedCharacter { ref path, .. } => {
+                assert_eq!(path, r#"$['a\nbA'][0]"#);
+            },
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_path_between_values() {
+        fn path(source: &str) -> String {
+            match parse(source).unwrap_err() {
+                JsonError::UnexpectedCharacter { path, .. } |
+                JsonError::UnexpectedEndOfJson { path, .. } => path,
+                err => panic!("Unexpected error: {:?}", err),
+            }
+        }
+
+        assert_eq!(path("[1, [2, 3] x]"), "$");
+        assert_eq!(path("[1, [2, 3x]"), "$[1]");
+        assert_eq!(path("[1, -x]"), "$[1]");
+        assert_eq!(path(r#"{"a": 1 "b": 2}"#), "$");
+        assert_eq!(path(r#"{"a": 1, "b" 2}"#), "$");
+        assert_eq!(path(r#"{"a": {}, "b": tru}"#), "$['b']");
+        assert_eq!(path(r#"{"a\q": 1}"#), "$");
+        assert_eq!(path(r#"{"a": "\q"}"#), "$['a']");
+        assert_eq!(path(r#"{"a": "[{\",", "b\"]": [0, {"c": [x]}]}"#), r#"$['b"]'][1]['c'][0]"#);
+        assert_eq!(path(r#"["\\", {"\\": "\"}"#), r#"$[1]['\\']"#);
+        assert_eq!(path("[[], {}, [[]], "), "$[3]");
+        assert_eq!(path(r#""unterminated"#), "$");
+        assert_eq!(path("[1] 2"), "$");
+    }
+
+    #[test]
+    fn error_expected_tokens() {
+        fn expected(source: &str) -> &'static [&'static str] {
+            match parse(source).unwrap_err() {
+                JsonError::UnexpectedCharacter { expected, .. } |
+                JsonError::UnexpectedEndOfJson { expected, .. } => expected,
+                err => panic!("Unexpected error: {:?}", err),
+            }
+        }
+
+        assert_eq!(expected(""), &["value"]);
+        assert_eq!(expected("[1 2]"), &["','", "']'"]);
+        assert_eq!(expected("{1:2}"), &["string", "'}'"]);
+        assert_eq!(expected(r#"{"a" 2}"#), &["':'"]);
+        assert_eq!(expected(r#"{"a":2,}"#), &["string"]);
+        assert_eq!(expected(r#"{"a":2 "b"}"#), &["','", "'}'"]);
+        assert_eq!(expected(r#""\x""#), &["escape sequence"]);
+        assert_eq!(expected(r#""\u12x4""#), &["hex digit"]);
+        assert_eq!(expected(r#""foo"#), &["'\"'"]);
+        assert_eq!(expected("-x"), &["digit"]);
+        assert_eq!(expected("1e"), &["digit"]);
+        assert_eq!(expected("[] x"), &["end of JSON"]);
+    }
+
+    #[test]
+    fn error_snippet() {
+        let source = "{\n\t\"foo\": trux\n}";
+        let err = parse(source).unwrap_err();
+
+        assert_eq!(err.snippet(source).unwrap(), "2 | \t\"foo\": trux\n  | \t          ^");
+
+        let source = "[1,";
+        let err = parse(source).unwrap_err();
+
+        assert_eq!(err.snippet(source).unwrap(), "1 | [1,\n  |    ^");
+
+        assert_eq!(JsonError::wrong_type("Array").snippet(source), None);
+    }
+
+    #[test]
+    fn error_kind_and_code() {
+        let err = parse("[1, x]").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Syntax);
+        assert_eq!(err.code(), "unexpected_character");
+
+        let err = parse(r#""\uD800\u0041""#).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Encoding);
+        assert_eq!(err.code(), "failed_utf8_parsing");
+
+        let err = JsonValue::from(10).push(20).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Type);
+        assert_eq!(err.code(), "wrong_type");
+    }
+
+    #[test]
+    fn error_clone_eq() {
+        let err = parse("[1, 2").unwrap_err();
+        let cloned = err.clone();
+
+        assert_eq!(err, cloned);
+        assert!(err != parse("[1, 23").unwrap_err());
+    }
+
+    #[test]
+    fn error_io() {
+        use std::error::Error;
+        use std::io;
+
+        let err = JsonError::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe"));
+
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.code(), "io");
+        assert_eq!(format!("{}", err), "I/O error: pipe");
+        assert_eq!(err, err.clone());
+
+        let source = err.source().expect("Should have a source");
+        assert_eq!(format!("{}", source), "pipe");
+
+        assert!(parse("x").unwrap_err().source().is_none());
+    }
+
+    #[test]
+    fn error_exceeded_depth_limit() {
+        let options = ParseOptions::new().max_depth(128);
+
+        let source = format!("{}{}", "[".repeat(129), "]".repeat(129));
+        let err = parse_with(&source, options).unwrap_err();
+
+        assert_eq!(err, JsonError::ExceededDepthLimit);
+        assert_eq!(err.kind(), ErrorKind::Limit);
+        assert!(parse(&source).is_ok());
+
+        let source = format!("{}{}", "[".repeat(128), "]".repeat(128));
+        assert!(parse_with(&source, options).is_ok());
+
+        let source = format!("{}{}", r#"{"a":"#.repeat(129), "}".repeat(129));
+        assert_eq!(parse_with(&source, options).unwrap_err(), JsonError::ExceededDepthLimit);
+
+        // There's no limit unless one is set
+        let source = format!("{}{}", "[".repeat(150), "]".repeat(150));
+        assert!(parse(&source).is_ok());
+
+        assert_eq!(parse_with("[]", ParseOptions::new().max_depth(0)).unwrap_err(), JsonError::ExceededDepthLimit);
+        assert!(parse_with("1", ParseOptions::new().max_depth(0)).is_ok());
+    }
+
     #[test]
     fn writer_generator() {
         let data = object!{
@@ -973,10 +2450,518 @@ mod unit {
 
         let mut buf = Vec::new();
 
-        data.to_writer(&mut buf);
+        data.to_writer(&mut buf).unwrap();
 
         assert_eq!(String::from_utf8(buf).unwrap(), r#"{"foo":["bar",100,true]}"#);
     }
+
+    struct CountingWriter {
+        buf: Vec<u8>,
+        writes: usize,
+        fail_after: Option<usize>,
+    }
+
+    impl io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Some(limit) = self.fail_after {
+                if self.buf.len() + buf.len() > limit {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+                }
+            }
+            self.writes += 1;
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writer_generator_buffered() {
+        let data = JsonValue::from((0..1000).collect::<Vec<i32>>());
+        let mut writer = CountingWriter { buf: Vec::new(), writes: 0, fail_after: None };
+
+        data.to_writer(&mut writer).unwrap();
+
+        assert_eq!(String::from_utf8(writer.buf).unwrap(), data.dump());
+        assert!(writer.writes <= 2);
+    }
+
+    #[test]
+    fn writer_generator_error() {
+        let data = JsonValue::from((0..10000).collect::<Vec<i32>>());
+        let mut writer = CountingWriter { buf: Vec::new(), writes: 0, fail_after: Some(100) };
+
+        let err = data.to_writer(&mut writer).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        let data = array!["small"];
+        let mut writer = CountingWriter { buf: Vec::new(), writes: 0, fail_after: Some(3) };
+
+        let err = data.to_writer(&mut writer).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    // Records the hooks called, instead of writing JSON
+    struct Recorder {
+        code: Vec<u8>,
+        events: Vec<String>,
+    }
+
+    impl Generator for Recorder {
+        type T = Vec<u8>;
+
+        fn get_writer(&mut self) -> &mut Vec<u8> {
+            &mut self.code
+        }
+
+        fn begin_array(&mut self) -> io::Result<()> {
+            self.events.push("begin_array".into());
+            Ok(())
+        }
+
+        fn begin_array_value(&mut self, first: bool) -> io::Result<()> {
+            self.events.push(format!("begin_array_value {}", first));
+            Ok(())
+        }
+
+        fn end_array(&mut self, empty: bool) -> io::Result<()> {
+            self.events.push(format!("end_array {}", empty));
+            Ok(())
+        }
+
+        fn begin_object(&mut self) -> io::Result<()> {
+            self.events.push("begin_object".into());
+            Ok(())
+        }
+
+        fn begin_object_key(&mut self, first: bool) -> io::Result<()> {
+            self.events.push(format!("begin_object_key {}", first));
+            Ok(())
+        }
+
+        fn write_key(&mut self, key: &str) -> io::Result<()> {
+            self.events.push(format!("key {}", key));
+            Ok(())
+        }
+
+        fn begin_object_value(&mut self) -> io::Result<()> {
+            self.events.push("begin_object_value".into());
+            Ok(())
+        }
+
+        fn end_object(&mut self, empty: bool) -> io::Result<()> {
+            self.events.push(format!("end_object {}", empty));
+            Ok(())
+        }
+
+        fn write_string(&mut self, string: &str) -> io::Result<()> {
+            self.events.push(format!("string {}", string));
+            Ok(())
+        }
+
+        fn write_number(&mut self, number: f64) -> io::Result<()> {
+            self.events.push(format!("number {}", number));
+            Ok(())
+        }
+
+        fn write_integer(&mut self, number: Integer) -> io::Result<()> {
+            self.events.push(format!("integer {}", number));
+            Ok(())
+        }
+
+        fn write_bool(&mut self, value: bool) -> io::Result<()> {
+            self.events.push(format!("bool {}", value));
+            Ok(())
+        }
+
+        fn write_null(&mut self) -> io::Result<()> {
+            self.events.push("null".into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_generator_hooks() {
+        let data = object!{ "a" => array![1, 2.5, true], "b" => object!{}, "c" => Null, "d" => "x" };
+        let mut gen = Recorder { code: Vec::new(), events: Vec::new() };
+
+        gen.write_json(&data).unwrap();
+
+        // Entries are written in whatever order the map backend keeps them in
+        let mut expected = vec!["begin_object"];
+
+        for (index, (key, _)) in data.entries().enumerate() {
+            expected.push(if index == 0 { "begin_object_key true" } else { "begin_object_key false" });
+            expected.extend(match key.as_str() {
+                "a" => vec![
+                    "key a", "begin_object_value",
+                    "begin_array",
+                    "begin_array_value true", "integer 1",
+                    "begin_array_value false", "number 2.5",
+                    "begin_array_value false", "bool true",
+                    "end_array false",
+                ],
+                "b" => vec!["key b", "begin_object_value", "begin_object", "end_object true"],
+                "c" => vec!["key c", "begin_object_value", "null"],
+                _   => vec!["key d", "begin_object_value", "string x"],
+            });
+        }
+
+        expected.push("end_object false");
+
+        assert_eq!(gen.events, expected);
+        assert!(gen.code.is_empty());
+    }
+
+    #[test]
+    fn builtin_generators() {
+        let data = object!{ "a" => array![1, "two"], "b" => Null };
+
+        let mut gen = DumpGenerator::new(DumpOptions::new());
+        gen.write_json(&data).unwrap();
+        assert_eq!(gen.consume(), data.dump());
+
+        let mut gen = PrettyGenerator::new(PrettyOptions::from(2));
+        gen.write_json(&data).unwrap();
+        assert_eq!(gen.consume(), data.pretty(2));
+
+        let mut buf = Vec::new();
+        WriterGenerator::new(&mut buf, DumpOptions::new()).write_json(&data).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.dump());
+
+        let mut buf = Vec::new();
+        PrettyWriterGenerator::new(&mut buf, PrettyOptions::from(2)).write_json(&data).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(2));
+
+        // Invalid UTF-8 can only come from `write`, and doesn't end up in the string
+        let mut gen = DumpGenerator::new(DumpOptions::new());
+        gen.write(&[b'"', 0xFF, b'"']).unwrap();
+        assert_eq!(gen.consume(), "\"\u{FFFD}\"");
+    }
+
+    #[test]
+    fn serialized_len_matches_output() {
+        let data = object!{
+            "strings" => array!["", "plain", "quote\" backslash\\", "\u{1}\u{1f}\t\n", "zażółć 😀", "<&'>\u{2028}"],
+            "numbers" => array![0, -0.0, 1, -1, 0.5, 1e-20, 1e20, 123456789012345680000.0, f64::NAN, f64::INFINITY],
+            "nested" => array![array![], object!{}, array![array![Null, true, false]]],
+            "long key with some length" => "x".repeat(200)
+        };
+
+        assert_eq!(data.serialized_len(), data.dump().len());
+
+        for options in &[DumpOptions::new().ascii_only(true), DumpOptions::new().html_safe(true),
+                         DumpOptions::new().non_finite(NonFinite::Literal)] {
+            assert_eq!(data.serialized_len_with(*options).unwrap(), data.dump_with(*options).unwrap().len());
+        }
+
+        let options = DumpOptions::new().non_finite(NonFinite::Error);
+        assert_eq!(data.serialized_len_with(options), Err(JsonError::NonFiniteNumber));
+
+        for options in &[PrettyOptions::from(4), PrettyOptions::from(0), PrettyOptions::new().indent(Indent::Tab),
+                         PrettyOptions::new().indent(Indent::None).space_after_comma(false),
+                         PrettyOptions::new().line_ending(LineEnding::CrLf).trailing_newline(true).compact_empty(false),
+                         PrettyOptions::from(2).max_width(40), PrettyOptions::from(2).max_width(80).ascii_only(true)] {
+            assert_eq!(data.serialized_len_pretty(*options), data.pretty(*options).len());
+        }
+
+        assert_eq!(JsonValue::from("😀").serialized_len(), 6);
+        assert_eq!(Null.serialized_len(), 4);
+    }
+
+    #[test]
+    fn summarize_within_limits() {
+        let data = object!{ "a" => array![1, 2], "b" => "text", "c" => Null };
+
+        assert_eq!(data.summarize(SummaryOptions::new()), data.dump());
+        assert_eq!(data.summarize(SummaryOptions::new().pretty(2)), data.pretty(2));
+    }
+
+    #[test]
+    fn summarize_limits() {
+        let data = array![
+            array![array![array![1]], object!{ "x" => object!{ "y" => 1 } }],
+            array![],
+            "ąąąąą",
+            (0..100).collect::<Vec<i32>>()
+        ];
+
+        let options = SummaryOptions::new().max_depth(2).max_items(3).max_string_len(5);
+
+        assert_eq!(data.summarize(options), r#"[[["…(1 more items)"],{"…":"(1 more entries)"}],[],"ąą…(6 more bytes)","…(1 more items)"]"#);
+
+        let options = SummaryOptions::new().max_items(3).max_string_len(5).max_depth(3);
+        assert_eq!(data[3].summarize(options), r#"[0,1,2,"…(97 more items)"]"#);
+
+        let options = SummaryOptions::new().max_items(2);
+        assert_eq!(data.summarize(options), r#"[[[[1]],{"x":{"y":1}}],[],"…(2 more items)"]"#);
+
+        let options = SummaryOptions::new().max_depth(0);
+        assert_eq!(data.summarize(options), r#"["…(4 more items)"]"#);
+
+        // Which entries of an object are kept depends on the map backend
+        let object = object!{ "a" => 1, "b" => 2, "c" => 3, "d" => 4 };
+        let summary = parse(&object.summarize(SummaryOptions::new().max_items(2))).unwrap();
+
+        assert_eq!(summary.len(), 3);
+        assert_eq!(summary["…"], "(2 more entries)");
+
+        for (key, value) in summary.entries().filter(|&(key, _)| key != "…") {
+            assert_eq!(object[key.as_str()], *value);
+        }
+
+        let options = SummaryOptions::new().max_depth(0);
+        assert_eq!(object.summarize(options), r#"{"…":"(4 more entries)"}"#);
+
+        let keys = object!{ "long key" => 1 };
+        assert_eq!(keys.summarize(SummaryOptions::new().max_string_len(4)), r#"{"long…(4 more bytes)":1}"#);
+    }
+
+    #[test]
+    fn summarize_max_bytes() {
+        let data = JsonValue::from((0..1_000_000).collect::<Vec<i32>>());
+        let summary = data.summarize(SummaryOptions::new().max_items(usize::MAX).max_bytes(100));
+
+        assert!(summary.len() < 150);
+        assert!(summary.ends_with(r#"more items)"]"#));
+
+        let parsed = parse(&summary).unwrap();
+        let shown = parsed.len() - 1;
+        assert_eq!(parsed[shown], format!("…({} more items)", 1_000_000 - shown));
+
+        let pretty = data.summarize(SummaryOptions::new().max_items(3).pretty(2));
+        assert_eq!(pretty, "[\n  0,\n  1,\n  2,\n  \"…(999997 more items)\"\n]");
+    }
+
+    fn strip_colors(colored: &str) -> String {
+        let mut plain = String::new();
+        let mut escape = false;
+
+        for ch in colored.chars() {
+            match ch {
+                '\x1b'        => escape = true,
+                'm' if escape => escape = false,
+                _ if escape   => {},
+                _             => plain.push(ch),
+            }
+        }
+
+        plain
+    }
+
+    #[test]
+    fn writer_generator_colored() {
+        let data = object!{ "a" => array![1, "two", true, Null, object!{}] };
+
+        let mut buf = Vec::new();
+        data.to_writer_colored(&mut buf, 2, Theme::new()).unwrap();
+        let colored = String::from_utf8(buf).unwrap();
+
+        assert_eq!(colored, "{\n  \x1b[34m\"a\"\x1b[0m: [\n    \x1b[36m1\x1b[0m,\n    \x1b[32m\"two\"\x1b[0m,\n    \x1b[33mtrue\x1b[0m,\n    \x1b[38;5;8mnull\x1b[0m,\n    {}\n  ]\n}");
+        assert_eq!(strip_colors(&colored), data.pretty(2));
+    }
+
+    #[test]
+    fn writer_generator_colored_options() {
+        let data = array![object!{ "k" => -1.5 }, array![], "esc\"aped"];
+        let theme = Theme::plain().punctuation(Color::Fixed(244)).number(Color::Rgb(1, 2, 3));
+
+        for options in &[PrettyOptions::from(4), PrettyOptions::new().indent(Indent::None),
+                         PrettyOptions::new().indent(Indent::Tab).compact_empty(false).trailing_newline(true)] {
+            let mut buf = Vec::new();
+            data.to_writer_colored(&mut buf, *options, theme).unwrap();
+            let colored = String::from_utf8(buf).unwrap();
+
+            assert!(colored.contains("\x1b[38;2;1;2;3m-1.5\x1b[0m"));
+            assert!(colored.contains("\x1b[38;5;244m[\x1b[0m"));
+            assert_eq!(strip_colors(&colored), data.pretty(*options));
+        }
+
+        let mut buf = Vec::new();
+        data.to_writer_colored(&mut buf, 2, Theme::plain()).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(2));
+    }
+
+    #[test]
+    fn writer_generator_colored_if() {
+        let data = object!{ "a" => array![1, "two"] };
+        let mut buf = Vec::new();
+
+        data.to_writer_colored_if(&mut buf, false, 2, Theme::new()).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), data.pretty(2));
+
+        let mut buf = Vec::new();
+        data.to_writer_colored_if(&mut buf, true, 2, Theme::new()).unwrap();
+        let written = String::from_utf8(buf).unwrap();
+
+        assert_eq!(strip_colors(&written), data.pretty(2));
+
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            assert_eq!(written, data.pretty(2));
+        } else {
+            assert!(written.contains("\x1b["));
+        }
+    }
+
+    fn write_sample<W: io::Write>(writer: &mut JsonWriter<W>) -> Result<(), JsonError> {
+        try!(writer.begin_object());
+        try!(writer.key("empty"));
+        try!(writer.begin_array());
+        try!(writer.end_array());
+        try!(writer.key("items"));
+        try!(writer.begin_array());
+        try!(writer.number(1.5));
+        try!(writer.string("two\n"));
+        try!(writer.boolean(false));
+        try!(writer.null());
+        try!(writer.value(&object!{ "nested" => array![1, 2] }));
+        try!(writer.begin_object());
+        try!(writer.end_object());
+        try!(writer.end_array());
+        try!(writer.key("name"));
+        try!(writer.string("json"));
+        writer.end_object()
+    }
+
+    fn sample() -> JsonValue {
+        object!{
+            "empty" => array![],
+            "items" => array![1.5, "two\n", false, Null, object!{ "nested" => array![1, 2] }, object!{}],
+            "name" => "json"
+        }
+    }
+
+    #[test]
+    fn json_writer_compact() {
+        let mut writer = JsonWriter::new(Vec::new());
+        write_sample(&mut writer).unwrap();
+
+        assert_json_eq(&String::from_utf8(writer.finish().unwrap()).unwrap(), &sample().dump());
+
+        let options = DumpOptions::new().ascii_only(true);
+        let mut writer = JsonWriter::with_options(Vec::new(), options);
+        writer.string("ünïcödé").unwrap();
+
+        assert_eq!(writer.finish().unwrap(), br#""\u00fcn\u00efc\u00f6d\u00e9""#.to_vec());
+    }
+
+    #[test]
+    fn json_writer_pretty() {
+        let options = PrettyOptions::from(2).trailing_newline(true);
+        let mut writer = JsonWriter::pretty(Vec::new(), options);
+        write_sample(&mut writer).unwrap();
+
+        assert_json_eq(&String::from_utf8(writer.finish().unwrap()).unwrap(), &sample().pretty(options));
+
+        let options = PrettyOptions::new().indent(Indent::Tab).compact_empty(false);
+        let mut writer = JsonWriter::pretty(Vec::new(), options);
+        write_sample(&mut writer).unwrap();
+
+        assert_json_eq(&String::from_utf8(writer.finish().unwrap()).unwrap(), &sample().pretty(options));
+    }
+
+    #[test]
+    fn json_writer_integer() {
+        let mut writer = JsonWriter::new(Vec::new());
+
+        writer.begin_array().unwrap();
+        writer.integer(u64::MAX).unwrap();
+        writer.integer(i64::MIN).unwrap();
+        writer.integer(9007199254740993u64).unwrap();
+        writer.integer(Integer::from(-7)).unwrap();
+        writer.end_array().unwrap();
+
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(output, "[18446744073709551615,-9223372036854775808,9007199254740993,-7]");
+        assert_eq!(output, array![u64::MAX, i64::MIN, 9007199254740993u64, -7].dump());
+
+        let mut writer = JsonWriter::pretty(Vec::new(), 2);
+
+        writer.begin_array().unwrap();
+        writer.integer(u64::MAX).unwrap();
+        writer.integer(i64::MIN).unwrap();
+        writer.end_array().unwrap();
+
+        assert_eq!(String::from_utf8(writer.finish().unwrap()).unwrap(),
+                   "[\n  18446744073709551615,\n  -9223372036854775808\n]");
+    }
+
+    #[test]
+    fn json_writer_misuse() {
+        let mut writer = JsonWriter::new(Vec::new());
+        assert_eq!(writer.key("a"), Err(JsonError::InvalidWrite("key outside of an object")));
+        assert_eq!(writer.end_array(), Err(JsonError::InvalidWrite("closing a container that isn't open")));
+
+        writer.begin_object().unwrap();
+        assert_eq!(writer.number(1.0), Err(JsonError::InvalidWrite("value where a key was expected")));
+        assert_eq!(writer.end_array(), Err(JsonError::InvalidWrite("closing a different container than the open one")));
+
+        writer.key("a").unwrap();
+        assert_eq!(writer.key("b"), Err(JsonError::InvalidWrite("key where a value was expected")));
+        assert_eq!(writer.end_object(), Err(JsonError::InvalidWrite("closing an object after a key without a value")));
+
+        writer.begin_array().unwrap();
+        assert_eq!(writer.key("c").unwrap_err().kind(), ErrorKind::Syntax);
+        writer.end_array().unwrap();
+        writer.end_object().unwrap();
+
+        assert_eq!(writer.null(), Err(JsonError::InvalidWrite("more than one value at the top level")));
+        assert_eq!(writer.finish().unwrap(), b"{\"a\":[]}".to_vec());
+
+        assert_eq!(JsonWriter::new(Vec::new()).finish().unwrap_err(), JsonError::InvalidWrite("nothing was written"));
+
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.begin_array().unwrap();
+        assert_eq!(writer.finish().unwrap_err(), JsonError::InvalidWrite("unclosed array or object"));
+    }
+
+    #[test]
+    fn json_writer_error() {
+        let options = DumpOptions::new().non_finite(NonFinite::Error);
+        let mut writer = JsonWriter::with_options(Vec::new(), options);
+        writer.begin_array().unwrap();
+        assert_eq!(writer.number(f64::NAN), Err(JsonError::NonFiniteNumber));
+        assert_eq!(writer.value(&array![1, f64::INFINITY]), Err(JsonError::NonFiniteNumber));
+        writer.number(1.0).unwrap();
+        writer.begin_object().unwrap();
+        writer.key("a").unwrap();
+        assert_eq!(writer.number(f64::NAN), Err(JsonError::NonFiniteNumber));
+        writer.number(2.0).unwrap();
+        writer.key("b").unwrap();
+        writer.number(3.0).unwrap();
+        writer.end_object().unwrap();
+        writer.end_array().unwrap();
+        assert_eq!(writer.finish().unwrap(), b"[1,{\"a\":2,\"b\":3}]".to_vec());
+
+        let options = PrettyOptions::new().non_finite(NonFinite::Error);
+        let mut writer = JsonWriter::pretty(Vec::new(), options);
+        writer.begin_array().unwrap();
+        assert_eq!(writer.number(f64::NEG_INFINITY), Err(JsonError::NonFiniteNumber));
+        writer.number(1.0).unwrap();
+        writer.end_array().unwrap();
+        assert_eq!(writer.finish().unwrap(), b"[\n    1\n]".to_vec());
+
+        let mut writer = JsonWriter::new(CountingWriter { buf: Vec::new(), writes: 0, fail_after: Some(10) });
+        writer.begin_array().unwrap();
+
+        for _ in 0..10000 {
+            if let Err(err) = writer.string("some text") {
+                assert_eq!(err.kind(), ErrorKind::Io);
+                assert_eq!(writer.string("more"), Err(JsonError::InvalidWrite("writing after an earlier write failed")));
+                assert_eq!(writer.end_array(), Err(JsonError::InvalidWrite("writing after an earlier write failed")));
+                assert!(writer.finish().is_err());
+                return;
+            }
+        }
+
+        panic!("Writer didn't fail");
+    }
 }
 
 mod json_checker_fail {